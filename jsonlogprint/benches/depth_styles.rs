@@ -0,0 +1,74 @@
+//! Compare `Styler::depth_style`, which looks up the prebuilt `DEPTH_STYLES`,
+//! with building the style for every key like it used to
+//!
+//! Run with `cargo bench --bench depth_styles`.
+
+// Only a little of the modules pulled in from the binary is used here
+#![allow(dead_code, unused_imports)]
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use fnv::FnvBuildHasher;
+use indexmap::IndexMap;
+use owo_colors::AnsiColors;
+
+#[path = "../src/cfg.rs"]
+mod cfg;
+#[path = "../src/deser.rs"]
+mod deser;
+#[path = "../src/filter.rs"]
+mod filter;
+#[path = "../src/path.rs"]
+mod path;
+#[path = "../src/styler.rs"]
+mod styler;
+#[path = "../src/template.rs"]
+mod template;
+
+use styler::{Markup, Paint, Styler};
+
+type FnvIndexMap<K, V> = IndexMap<K, V, FnvBuildHasher>;
+
+/// `Styler::depth_style` before the styles were prebuilt
+fn per_call_depth_style(styler: &Styler, depth: u16) -> Paint {
+    if !styler.colorize {
+        return Paint::new();
+    }
+    match depth % 6 {
+        0 => Paint::new().fg(AnsiColors::Blue),
+        1 => Paint::new().fg(AnsiColors::Cyan),
+        2 => Paint::new().fg(AnsiColors::Green),
+        3 => Paint::new().fg(AnsiColors::Blue).dimmed(),
+        4 => Paint::new().fg(AnsiColors::Cyan).dimmed(),
+        5 => Paint::new().fg(AnsiColors::Green).dimmed(),
+        _ => Paint::new(),
+    }
+}
+
+fn depth_styles(c: &mut Criterion) {
+    let styler = Styler {
+        colorize: true,
+        markup: Markup::Ansi,
+        max_depth: None,
+    };
+    let mut group = c.benchmark_group("depth_style");
+    group.bench_function("prebuilt", |b| {
+        b.iter(|| {
+            for depth in 0..64 {
+                black_box(styler.depth_style(black_box(depth)));
+            }
+        })
+    });
+    group.bench_function("per_call", |b| {
+        b.iter(|| {
+            for depth in 0..64 {
+                black_box(per_call_depth_style(&styler, black_box(depth)));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, depth_styles);
+criterion_main!(benches);
@@ -0,0 +1,53 @@
+//! Compare rendering a stream of millisecond timestamps with
+//! `--lock-timestamp-unit` against detecting the unit of every record
+//!
+//! Run with `cargo bench --bench lock_timestamp_unit`.
+
+use std::fs::File;
+use std::io::Write as _;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+const LINES: u64 = 100_000;
+
+/// Render `input` with the built binary, discarding the output
+fn render(input: &Path, args: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_jsonlogprint"))
+        .args(["--color=never"])
+        .args(args)
+        .stdin(File::open(input).unwrap())
+        .stdout(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+fn lock_timestamp_unit(c: &mut Criterion) {
+    let input = std::env::temp_dir().join(format!("jlp-bench-{}.json", std::process::id()));
+    let mut file = File::create(&input).unwrap();
+    for i in 0..LINES {
+        let timestamp = 1627494000000 + i;
+        writeln!(
+            file,
+            r#"{{"timestamp":{timestamp},"level":"info","msg":"line {i}"}}"#
+        )
+        .unwrap();
+    }
+    drop(file);
+
+    let mut group = c.benchmark_group("timestamp_unit");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(LINES));
+    group.bench_function("per_record", |b| b.iter(|| render(&input, &[])));
+    group.bench_function("locked", |b| {
+        b.iter(|| render(&input, &["--lock-timestamp-unit"]))
+    });
+    group.finish();
+
+    std::fs::remove_file(&input).unwrap();
+}
+
+criterion_group!(benches, lock_timestamp_unit);
+criterion_main!(benches);
@@ -1,6 +1,16 @@
 use chrono::format::Item;
 use chrono::format::StrftimeItems;
-use clap::{Parser, ValueEnum};
+use chrono::{DateTime, FixedOffset, Local, Utc};
+use clap::{CommandFactory, Parser, ValueEnum};
+use owo_colors::AnsiColors;
+use regex::Regex;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::filter::Filter;
+use crate::path::{Field, FieldPath};
+use crate::styler::{Level, NamedStyle};
+use crate::template::Template;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -15,12 +25,15 @@ pub(crate) struct Args {
     pub(crate) no_key_fields: Vec<String>,
 
     /// Color output settings: always, auto, never
+    ///
+    /// auto doesn't color the output if `NO_COLOR` is set.
     #[arg(long, value_enum, default_value = "auto")]
     pub(crate) color: ColorOption,
 
     /// Timestamp format.
     ///
-    /// Auto, Seconds or Millis will be converted to ISO format in output,
+    /// Auto, Seconds, Millis or Nanos will be converted to ISO format in output,
+    /// Uptime is seconds since boot and is shown as `+HH:MM:SS.mmm`,
     /// Raw means it is not processed.
     #[arg(long, visible_alias = "tsfmt", value_enum, default_value = "auto")]
     pub(crate) timestamp_format: TimestampFormat,
@@ -33,8 +46,599 @@ pub(crate) struct Args {
 
     /// The field to use as the log level.
     /// If the field is a string, it will be colorized.
+    ///
+    /// A nested level can be given as a dotted path like `meta.level`, or a
+    /// JSON Pointer like `/meta/level`.
     #[arg(long, default_value = "level")]
     pub(crate) level_field: String,
+
+    /// Output format.
+    ///
+    /// Text is for terminals, Html emits `<span>` elements with inline styles
+    /// for pasting into wikis and tickets. AnsiToFile always embeds ANSI
+    /// escapes, even when not writing to a terminal, so that colored output
+    /// captured with --output can be replayed later with `cat` or `less -R`.
+    /// Json writes each record as a line of JSON, for further processing.
+    #[arg(long, value_enum, default_value = "text")]
+    pub(crate) output_format: OutputFormat,
+
+    /// Write output to this file instead of stdout
+    #[arg(short, long)]
+    pub(crate) output: Option<PathBuf>,
+
+    /// Stop coloring nested keys deeper than this, to reduce the volume of
+    /// escape codes for deeply nested objects
+    #[arg(long)]
+    pub(crate) color_max_depth: Option<usize>,
+
+    /// Number of sub-second digits to show in formatted timestamps: 0, 3, 6 or 9
+    ///
+    /// By default millisecond timestamps show 3 digits and second timestamps
+    /// show none.
+    #[arg(long, visible_alias = "timestamp-precision", value_parser = parse_subsecond)]
+    pub(crate) subsecond: Option<u8>,
+
+    /// A JSON Pointer (like `/fields/message`) to a nested string to show as
+    /// the message, without a key, after the --no-key-fields.
+    ///
+    /// The nested value is removed from its parent so that it isn't printed
+    /// twice.
+    #[arg(long, value_parser = FieldPath::from_pointer)]
+    pub(crate) message_pointer: Option<FieldPath>,
+
+    /// Only show the first N elements of arrays, followed by a count of the
+    /// elements that were hidden
+    #[arg(long, value_name = "N")]
+    pub(crate) summarize_arrays: Option<usize>,
+
+    /// Only print --no-key-fields without a key when they lead the record
+    ///
+    /// A field is printed without a key if it, and every key before it in the
+    /// JSON object, are in --no-key-fields. Once any other key has been seen
+    /// the remaining --no-key-fields are printed with their key, in place.
+    /// Missing fields don't affect this, so `{"level":..,"msg":..,"user":..}`
+    /// prints `level` and `msg` without keys whether or not there is a
+    /// `timestamp`.
+    #[arg(long)]
+    pub(crate) no_key_fields_only_if_first: bool,
+
+    /// A field, like `logger` or `component`, to highlight with its own color
+    #[arg(long)]
+    pub(crate) component_field: Option<String>,
+
+    /// Print floating point numbers like `1e6` in plain decimal notation
+    ///
+    /// Whole numbers are printed without a fractional part. Numbers too large
+    /// or small to sensibly expand (outside 1e-16..1e21) are left as-is.
+    #[arg(long)]
+    pub(crate) expand_scientific: bool,
+
+    /// Write error and critical records to this stream, and all other lines
+    /// to the other one
+    ///
+    /// With --output, stdout means the output file.
+    #[arg(long, value_enum)]
+    pub(crate) errors_to: Option<OutputStream>,
+
+    /// Prefix each line with its line number in the input
+    ///
+    /// Objects joined from several lines get the number of their first line.
+    #[arg(long)]
+    pub(crate) line_numbers: bool,
+
+    /// Show the message of the first error or critical record with each
+    /// distinct message in bold, so new errors stand out while tailing
+    ///
+    /// The message is the --message-pointer value if set, otherwise the first
+    /// string --no-key-fields field that isn't the level or timestamp.
+    #[arg(long)]
+    pub(crate) highlight_new_errors: bool,
+
+    /// The quotes to use around values containing spaces or quotes
+    #[arg(long, value_enum, default_value = "double")]
+    pub(crate) quote_style: QuoteStyle,
+
+    /// Strip leading and trailing whitespace from keys, so `" level "` is
+    /// treated as `level`
+    #[arg(long)]
+    pub(crate) trim_keys: bool,
+
+    /// Guess the timestamp unit, level field and message field from the first
+    /// few lines of input
+    ///
+    /// The first lines are buffered until enough have been read to guess from.
+    #[arg(long)]
+    pub(crate) auto_detect: bool,
+
+    /// Fields whose string values get a color picked by hashing the value, so
+    /// that the same value, like a request id, is always the same color
+    #[arg(long, value_delimiter = ',')]
+    pub(crate) hash_color_field: Vec<String>,
+
+    /// Print a blank line after each JSON record, lines that aren't JSON are
+    /// printed as-is
+    #[arg(long)]
+    pub(crate) spacing: bool,
+
+    /// Only show the time of timestamps on the same day as the previous
+    /// record, the full date is shown whenever it changes
+    ///
+    /// Can't be combined with --ts-out-format, which has no time-only part.
+    #[arg(long, conflicts_with = "ts_out_format")]
+    pub(crate) compact_timestamp: bool,
+
+    /// Show numeric fields whose names end in a unit, like `latency_ms`, as
+    /// human readable durations
+    ///
+    /// Recognized suffixes are `_ns`, `_us`, `_ms` and `_sec`.
+    #[arg(long)]
+    pub(crate) auto_humanize: bool,
+
+    /// Render each top-level field as soon as it is parsed instead of first
+    /// collecting the whole record
+    ///
+    /// The whole line is still read, and each top-level value is parsed in
+    /// full before it is rendered. This disables reordering: fields are shown
+    /// in the order they appear, with --no-key-fields shown without their key
+    /// in place, and multi-line values are not moved to the end. Options that
+    /// need the whole record, like --exclude-fields and --template, can't be
+    /// used with it, and neither can --output-format=json.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "min_level",
+            "filter",
+            "require",
+            "exclude_fields",
+            "only_fields",
+            "field_default",
+            "template",
+            "hide_info_level",
+        ]
+    )]
+    pub(crate) stream_render: bool,
+
+    /// Print a sample record for each level and a nested object showing the
+    /// color of each depth, then exit
+    #[arg(long)]
+    pub(crate) color_test: bool,
+
+    /// Order the keyed fields by numeric value, largest first, for metrics
+    ///
+    /// Fields that aren't numbers are shown after the numbers, sorted by key.
+    #[arg(long)]
+    pub(crate) sort_by_value: bool,
+
+    /// The separator between top-level keyed fields, like `\t` or ` | `, to
+    /// make output easier to split into columns
+    ///
+    /// Fields inside nested objects and arrays are still separated by spaces.
+    #[arg(long, default_value = " ", value_parser = parse_separator)]
+    pub(crate) separator: String,
+
+    /// Write records to a file per level, like `info.log` and `error.log`, in
+    /// this directory instead of to stdout
+    ///
+    /// Lines without a level are written to `other.log`. Files are only
+    /// created once a record for them is seen.
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["output", "errors_to"])]
+    pub(crate) split_by_level: Option<PathBuf>,
+
+    /// Don't render anything until a line matching this regex is seen, the
+    /// matching line itself isn't rendered
+    #[arg(long, value_name = "REGEX")]
+    pub(crate) start_after: Option<Regex>,
+
+    /// Stop after rendering a line matching this regex
+    #[arg(long, value_name = "REGEX")]
+    pub(crate) stop_after: Option<Regex>,
+
+    /// Only render the first N records
+    #[arg(long, value_name = "N")]
+    pub(crate) head: Option<usize>,
+
+    /// Only render the last N records, nothing is shown until the input ends
+    #[arg(long, value_name = "N", conflicts_with = "head")]
+    pub(crate) tail: Option<usize>,
+
+    /// Only show lines matching this regex
+    #[arg(long, value_name = "REGEX")]
+    pub(crate) grep: Option<Regex>,
+
+    /// Only show records at or above this level
+    ///
+    /// Records without a recognizable level are always shown, even with
+    /// --invert.
+    #[arg(long, value_enum, ignore_case = true)]
+    pub(crate) min_level: Option<MinLevel>,
+
+    /// Negate the filters, like `grep -v`, so only lines that don't match
+    /// --grep, --min-level and --filter are shown
+    #[arg(short = 'v', long)]
+    pub(crate) invert: bool,
+
+    /// Collapse objects and arrays nested deeper than this, showing how many
+    /// keys or items were hidden, like `nested{…3 keys}`
+    ///
+    /// 0 collapses every value of the top-level keys.
+    #[arg(long, value_name = "N")]
+    pub(crate) max_depth: Option<usize>,
+
+    /// Text to put at the start of every output line, to tell apart the
+    /// output of several jsonlogprints in one terminal
+    ///
+    /// The prefix is colored by its hash, so different prefixes usually get
+    /// different colors.
+    #[arg(long)]
+    pub(crate) prefix: Option<String>,
+
+    /// Replace invalid UTF-8 in the input with `�` instead of printing a
+    /// blank line for lines that contain it
+    #[arg(long)]
+    pub(crate) lossy: bool,
+
+    /// Where to show the timestamp: at the front of the line with the other
+    /// --no-key-fields, or at the end of the line after all other fields
+    #[arg(long, value_enum, default_value = "front")]
+    pub(crate) timestamp_position: Position,
+
+    /// Print the original JSON, indented, below each rendered record
+    #[arg(long)]
+    pub(crate) keep_raw: bool,
+
+    /// Log a warning when the level or timestamp field has a value of the
+    /// wrong type, like an object, to help spot misconfigured field names
+    ///
+    /// Warnings are written to stderr, filtered by `JLP_LOG_FILTER`.
+    #[arg(long)]
+    pub(crate) warn_type_mismatch: bool,
+
+    /// Quote --no-key-fields values that contain spaces or quotes, the same way
+    /// as keyed values
+    #[arg(long)]
+    pub(crate) quote_header: bool,
+
+    /// With --timestamp-format=auto, detect whether timestamps are seconds or
+    /// millis from the first few lines and use that unit for the whole stream
+    /// instead of checking every line
+    #[arg(long)]
+    pub(crate) lock_timestamp_unit: bool,
+
+    /// Only show records matching an expression, like
+    /// `level == "error" && status >= 500`
+    ///
+    /// Fields, which may be dotted paths like `http.status`, are compared to
+    /// strings, numbers, `true`, `false` or `null` with `==`, `!=`, `<`, `<=`,
+    /// `>` or `>=`. A field on its own matches if it exists and isn't `false`
+    /// or `null`. Combine comparisons with `&&`, `||`, `!` and parentheses.
+    #[arg(long, value_name = "EXPRESSION", value_parser = Filter::parse)]
+    pub(crate) filter: Option<Filter>,
+
+    /// Only show the first N keyed fields of each record, followed by a count
+    /// of the fields that were hidden
+    #[arg(long, value_name = "N")]
+    pub(crate) max_fields_shown: Option<usize>,
+
+    /// Give up on rendering a record that takes longer than this many
+    /// milliseconds, and print the original line instead
+    ///
+    /// This is a safety valve for pathological input, like huge deeply nested
+    /// objects.
+    #[arg(long, value_name = "MS", value_parser = parse_millis)]
+    pub(crate) render_timeout: Option<Duration>,
+
+    /// Text to show for empty records, `{}`, instead of an empty line
+    ///
+    /// Not used with --template or --output-format=json.
+    #[arg(long, value_name = "TEXT")]
+    pub(crate) empty_placeholder: Option<String>,
+
+    /// Join objects that are spread over several lines, like pretty printed
+    /// JSON, by counting braces from a line starting with `{`
+    #[arg(long)]
+    pub(crate) combine_consecutive_json: bool,
+
+    /// Color a numeric field from green, at or below `low`, through yellow to
+    /// red, at or above `high`, like `--gradient latency_ms:100:1000`
+    ///
+    /// May be given more than once.
+    #[arg(long, value_name = "FIELD:LOW:HIGH", value_parser = Gradient::parse)]
+    pub(crate) gradient: Vec<Gradient>,
+
+    /// Log a warning with the line number and key when a record has the same
+    /// key more than once, only the last value of a duplicate key is shown
+    #[arg(long)]
+    pub(crate) warn_duplicate_keys: bool,
+
+    /// Text to put before lines that aren't JSON, to tell them apart from
+    /// rendered records
+    #[arg(long, value_name = "TEXT")]
+    pub(crate) passthrough_prefix: Option<String>,
+
+    /// A value for a field that's missing from a record, like
+    /// `--field-default env=prod`
+    ///
+    /// May be given more than once.
+    #[arg(long, value_name = "FIELD=VALUE", value_parser = parse_key_value)]
+    pub(crate) field_default: Vec<(String, String)>,
+
+    /// Show a top-level key with a different label, which may be any text
+    /// like `--label duration_ms=⏱`
+    ///
+    /// Only the displayed key changes, other options still use the original
+    /// key. May be given more than once.
+    #[arg(long, value_name = "FIELD=LABEL", value_parser = parse_key_value)]
+    pub(crate) label: Vec<(String, String)>,
+
+    /// How to show lines that start with `{` but aren't valid JSON: as they
+    /// are, or as a JSON object like `{"jlp_error":"...","line":"..."}`
+    #[arg(long, value_enum, default_value = "text")]
+    pub(crate) error_format: ErrorFormat,
+
+    /// Show a field holding an array of `[key, value]` pairs, like
+    /// `[["k1","v1"],["k2","v2"]]`, as `k1=v1 k2=v2`
+    ///
+    /// May be given more than once.
+    #[arg(long, value_name = "FIELD")]
+    pub(crate) pairs_field: Vec<String>,
+
+    /// Dim the whole line of records matching an expression, like
+    /// `path == "/healthz"`, to push noise into the background
+    ///
+    /// Uses the same expressions as --filter.
+    #[arg(long, value_name = "EXPRESSION", value_parser = Filter::parse)]
+    pub(crate) dim_when: Option<Filter>,
+
+    /// Show nothing but the number of JSON records, lines that aren't JSON
+    /// and lines that failed to parse, once the input ends
+    ///
+    /// Filters still apply, so this counts the lines that would be shown.
+    #[arg(long)]
+    pub(crate) count_lines: bool,
+
+    /// The order to print --no-key-fields in: the order they are listed in
+    /// --no-key-fields, or the order they appear in each record
+    #[arg(long, value_enum, default_value = "config")]
+    pub(crate) header_order: HeaderOrder,
+
+    /// Cut string values longer than this many bytes, followed by how many
+    /// more characters there are, like `…(1234 more)`
+    ///
+    /// Values are cut at a character boundary, so they may be a few bytes
+    /// shorter than the limit. --no-key-fields, like the message, are always
+    /// shown in full.
+    #[arg(long, value_name = "BYTES")]
+    pub(crate) max_value_bytes: Option<usize>,
+
+    /// Only show a field when its value is different from the previous record
+    /// that had it, for fields like a status that rarely change
+    ///
+    /// When an array changes, elements that were added are marked with `+` and
+    /// elements that were removed are listed at the end marked with `-`. May
+    /// be given more than once.
+    #[arg(long, value_name = "FIELD")]
+    pub(crate) show_changes_for: Vec<String>,
+
+    /// Render records with a template instead, like
+    /// `{timestamp} [{level}] {msg} ({request_id})`
+    ///
+    /// Each `{field}` is replaced with the value of the field, or nothing if
+    /// the record doesn't have it. Fields may be nested paths like
+    /// `{context.request_id}` or `{items.0}`. Use `{{` and `}}` for literal
+    /// braces.
+    ///
+    /// A style may follow the field after a `:`, like `{level:level}` to color
+    /// it by level or `{duration_ms:red}`. Styles are `level`, `bold`, `dim`,
+    /// `red`, `green`, `yellow`, `blue`, `magenta`, `cyan` and `gray`.
+    #[arg(long, value_parser = Template::parse)]
+    pub(crate) template: Option<Template>,
+
+    /// Skip blank input lines, empty records and lines that can't be read,
+    /// which would otherwise be shown as empty lines
+    ///
+    /// Blank lines that are part of a record, like the ones added by
+    /// --spacing, are still shown.
+    #[arg(long)]
+    pub(crate) no_blank_lines: bool,
+
+    /// Pass lines that aren't JSON but already contain ANSI escapes, like
+    /// colored output from other tools, through as they are
+    ///
+    /// They don't get the --passthrough-prefix. With `--output-format html`
+    /// the escapes are stripped and the rest of the line is escaped like any
+    /// other text.
+    #[arg(long)]
+    pub(crate) detect_and_skip_ansi_lines: bool,
+
+    /// Show integer values of a field in hex, like `flags=0xff`
+    ///
+    /// Negative numbers keep their sign, like `-0xff`, rather than being shown
+    /// in two's complement. May be given more than once.
+    #[arg(long, value_name = "FIELD")]
+    pub(crate) hex_field: Vec<String>,
+
+    /// Decode a field's value from base64 and show the text, if it is valid
+    /// UTF-8, otherwise the value is shown as it is
+    ///
+    /// May be given more than once.
+    #[arg(long, value_name = "FIELD")]
+    pub(crate) decode_base64: Vec<String>,
+
+    /// With --output-format=json, nest dotted keys, so `{"http.status":200}`
+    /// is written as `{"http":{"status":200}}`
+    ///
+    /// A dotted key whose parent already has a value that isn't an object is
+    /// written as it is.
+    #[arg(long)]
+    pub(crate) unflatten_dotted_output: bool,
+
+    /// Order the keyed fields of each record by key
+    #[arg(long)]
+    pub(crate) sort_keys: bool,
+
+    /// Order the keys of nested objects by key, independently of --sort-keys
+    #[arg(long)]
+    pub(crate) sort_nested_keys: bool,
+
+    /// The color of `null` values, like `gray`
+    #[arg(long, value_name = "COLOR", value_parser = parse_style)]
+    pub(crate) color_null: Option<NamedStyle>,
+
+    /// The colors of `true` and `false` values, like `green,red`
+    #[arg(long, value_name = "TRUE,FALSE", value_parser = parse_bool_styles)]
+    pub(crate) color_bool: Option<(NamedStyle, NamedStyle)>,
+
+    /// Show a field's values translated through a table, like
+    /// `--lookup status:200=OK,404=Not Found`
+    ///
+    /// Values that aren't in the table are shown as they are. May be given
+    /// more than once.
+    #[arg(long, value_name = "FIELD:VALUE=TEXT,...", value_parser = Lookup::parse)]
+    pub(crate) lookup: Vec<Lookup>,
+
+    /// Show a stack trace field, like `stacktrace` or `exception`, at the end
+    /// of the record with each frame on its own indented line
+    ///
+    /// Frames are split at newlines, and at tabs before `at ` for Java traces
+    /// that were logged on one line. May be given more than once.
+    #[arg(long, value_name = "FIELD")]
+    pub(crate) trace_field: Vec<String>,
+
+    /// Don't show the level of info records, which are usually the most
+    /// common, only the levels of other records
+    #[arg(long)]
+    pub(crate) hide_info_level: bool,
+
+    /// Show the fields of an object field, like `context`, as if they were
+    /// top-level fields, in place of the object
+    ///
+    /// May be given more than once.
+    #[arg(long, value_name = "FIELD")]
+    pub(crate) inline: Vec<String>,
+
+    /// Show `true` fields as just their key, like a flag, and hide `false`
+    /// fields
+    #[arg(long)]
+    pub(crate) bool_as_flag: bool,
+
+    /// Show how many keys or items objects and arrays have before their
+    /// contents, like `items[3][a b c]`
+    #[arg(long)]
+    pub(crate) show_sizes: bool,
+
+    /// Fields to never show, like `span_id,thread`
+    ///
+    /// This wins over --no-key-fields, excluded fields can still be used by
+    /// filters like --filter.
+    #[arg(long, value_delimiter = ',')]
+    pub(crate) exclude_fields: Vec<String>,
+
+    /// Instead of rendering records, show every field seen in the input with
+    /// the types of its values and an example value once the input ends
+    ///
+    /// Nested fields are shown by their path, like `http.status`, and array
+    /// elements like `tags[]`. Filters still apply.
+    #[arg(long, conflicts_with_all = ["count_lines", "stream_render"])]
+    pub(crate) discover_schema: bool,
+
+    /// Show only these fields, like `timestamp,level,msg,latency_ms`
+    ///
+    /// --no-key-fields that are listed are still shown first without their
+    /// key.
+    #[arg(long, value_delimiter = ',')]
+    pub(crate) only_fields: Option<Vec<String>>,
+
+    /// Stop after rendering N JSON records
+    ///
+    /// Unlike --head, lines that aren't JSON don't count towards the limit.
+    #[arg(long, value_name = "N")]
+    pub(crate) limit: Option<usize>,
+
+    /// The timezone to show timestamps in: utc, local or a name like
+    /// `America/New_York`
+    #[arg(long, default_value = "utc", value_parser = Timezone::parse)]
+    pub(crate) timezone: Timezone,
+
+    /// Color values by their type: strings green, numbers blue, bools
+    /// magenta and null gray
+    ///
+    /// --color-null and --color-bool still override the colors of nulls and
+    /// bools.
+    #[arg(long)]
+    pub(crate) color_by_type: bool,
+
+    /// Only show records that have this field, which may be a dotted path
+    /// like `context.request_id`
+    ///
+    /// May be given more than once to require every field.
+    #[arg(long, value_name = "FIELD")]
+    pub(crate) require: Vec<String>,
+
+    /// A strftime format for timestamps, like `%H:%M:%S%.3f`
+    ///
+    /// By default timestamps are shown as RFC 3339, with milliseconds for
+    /// millisecond timestamps.
+    #[arg(long, value_name = "FORMAT", conflicts_with = "subsecond", value_parser = parse_ts_out_format)]
+    pub(crate) ts_out_format: Option<String>,
+
+    /// Show the output in `$PAGER`, or `less -R` if it isn't set
+    ///
+    /// Only used when writing to a terminal, not with --output or
+    /// --split-by-level.
+    #[arg(long)]
+    pub(crate) pager: bool,
+
+    /// Only show records where this field changed since the last record that
+    /// was shown, like `status`
+    ///
+    /// Records without the field are hidden.
+    #[arg(long, value_name = "FIELD", conflicts_with = "stream_render")]
+    pub(crate) watch: Option<String>,
+
+    /// Shorten string values that look like long file paths to their first
+    /// and last components, like `/home/…/src/main.rs`
+    #[arg(long)]
+    pub(crate) shorten_paths: bool,
+
+    /// Fields to show as the message, without a key, after the
+    /// --no-key-fields, like `msg,message,text`
+    ///
+    /// Only the first of these that a record has is the message, the others
+    /// are shown like any other field.
+    #[arg(long, value_delimiter = ',', conflicts_with = "message_pointer")]
+    pub(crate) message_fields: Vec<String>,
+
+    /// Cut string values longer than this many characters, followed by how
+    /// many more there are, like `…(1234 more)`
+    ///
+    /// --no-key-fields, like the message, are always shown in full. With
+    /// --max-value-bytes the tighter of the two limits applies.
+    #[arg(long, value_name = "CHARS")]
+    pub(crate) max_value_width: Option<usize>,
+
+    /// Show the original value of numeric timestamps after the formatted
+    /// time, like `2021-07-28T17:40:00Z(1627494000)`
+    #[arg(long)]
+    pub(crate) dual_timestamp: bool,
+
+    /// Level names for numeric levels, like `30=info,40=warn`
+    ///
+    /// These are added to the pino and bunyan levels: 10=trace, 20=debug,
+    /// 30=info, 40=warn, 50=error and 60=critical.
+    #[arg(long, value_delimiter = ',', value_parser = parse_level_mapping)]
+    pub(crate) level_map: Vec<(i64, Level)>,
+
+    /// Like --combine-consecutive-json, but stop joining an object at a blank
+    /// line, a new line starting with `{` or after 1000 lines, and show the
+    /// lines as they are
+    #[arg(long)]
+    pub(crate) multiline: bool,
+
+    /// Flatten the keys of objects nested up to this deep into dotted
+    /// top-level fields, like `http.status=200`, keeping deeper objects in
+    /// braces
+    #[arg(long, value_name = "N")]
+    pub(crate) flatten_depth: Option<usize>,
 }
 
 #[derive(Debug)]
@@ -44,31 +648,475 @@ pub(crate) struct Config {
     pub(crate) timestamp_format: TimestampFormat,
     pub(crate) timestamp_field: String,
     pub(crate) level_field: String,
+    /// The --level-field as a path, if it is one
+    pub(crate) level_path: Option<FieldPath>,
+    pub(crate) output_format: OutputFormat,
+    pub(crate) output: Option<PathBuf>,
+    pub(crate) color_max_depth: Option<usize>,
+    pub(crate) message_pointer: Option<FieldPath>,
+    pub(crate) summarize_arrays: Option<usize>,
+    pub(crate) no_key_fields_only_if_first: bool,
+    pub(crate) component_field: Option<String>,
+    pub(crate) expand_scientific: bool,
+    pub(crate) errors_to: Option<OutputStream>,
+    pub(crate) line_numbers: bool,
+    pub(crate) highlight_new_errors: bool,
+    pub(crate) quote_style: QuoteStyle,
+    pub(crate) trim_keys: bool,
+    pub(crate) auto_detect: bool,
+    pub(crate) hash_color_fields: Vec<String>,
+    pub(crate) spacing: bool,
+    pub(crate) compact_timestamp: bool,
+    pub(crate) auto_humanize: bool,
+    pub(crate) stream_render: bool,
+    pub(crate) color_test: bool,
+    pub(crate) sort_by_value: bool,
+    pub(crate) separator: String,
+    pub(crate) split_by_level: Option<PathBuf>,
+    pub(crate) start_after: Option<Regex>,
+    pub(crate) stop_after: Option<Regex>,
+    pub(crate) head: Option<usize>,
+    pub(crate) tail: Option<usize>,
+    pub(crate) grep: Option<Regex>,
+    pub(crate) min_level: Option<Level>,
+    pub(crate) invert: bool,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) prefix: Option<String>,
+    pub(crate) lossy: bool,
+    pub(crate) timestamp_position: Position,
+    pub(crate) keep_raw: bool,
+    pub(crate) warn_type_mismatch: bool,
+    pub(crate) quote_header: bool,
+    pub(crate) lock_timestamp_unit: bool,
+    pub(crate) filter: Option<Filter>,
+    pub(crate) max_fields_shown: Option<usize>,
+    pub(crate) render_timeout: Option<Duration>,
+    pub(crate) empty_placeholder: Option<String>,
+    pub(crate) combine_consecutive_json: bool,
+    pub(crate) gradients: Vec<Gradient>,
+    pub(crate) warn_duplicate_keys: bool,
+    pub(crate) passthrough_prefix: Option<String>,
+    pub(crate) field_defaults: Vec<(String, String)>,
+    pub(crate) labels: Vec<(String, String)>,
+    pub(crate) error_format: ErrorFormat,
+    pub(crate) pairs_fields: Vec<String>,
+    pub(crate) dim_when: Option<Filter>,
+    pub(crate) count_lines: bool,
+    pub(crate) header_order: HeaderOrder,
+    pub(crate) max_value_bytes: Option<usize>,
+    pub(crate) show_changes_for: Vec<String>,
+    pub(crate) template: Option<Template>,
+    pub(crate) no_blank_lines: bool,
+    pub(crate) detect_and_skip_ansi_lines: bool,
+    pub(crate) hex_fields: Vec<String>,
+    pub(crate) decode_base64: Vec<String>,
+    pub(crate) unflatten_dotted_output: bool,
+    pub(crate) sort_keys: bool,
+    pub(crate) sort_nested_keys: bool,
+    pub(crate) color_null: Option<NamedStyle>,
+    pub(crate) color_bool: Option<(NamedStyle, NamedStyle)>,
+    pub(crate) lookups: Vec<Lookup>,
+    pub(crate) trace_fields: Vec<String>,
+    pub(crate) hide_info_level: bool,
+    pub(crate) inline_fields: Vec<String>,
+    pub(crate) bool_as_flag: bool,
+    pub(crate) show_sizes: bool,
+    pub(crate) exclude_fields: Vec<String>,
+    pub(crate) discover_schema: bool,
+    pub(crate) only_fields: Option<Vec<String>>,
+    pub(crate) limit: Option<usize>,
+    pub(crate) timezone: Timezone,
+    pub(crate) color_by_type: bool,
+    pub(crate) require: Vec<Field>,
+    pub(crate) pager: bool,
+    pub(crate) watch: Option<Field>,
+    pub(crate) shorten_paths: bool,
+    pub(crate) message_fields: Vec<String>,
+    pub(crate) max_value_width: Option<usize>,
+    pub(crate) dual_timestamp: bool,
+    /// Numeric levels and their names, later entries take precedence
+    pub(crate) level_map: Vec<(i64, Level)>,
+    pub(crate) multiline: bool,
+    pub(crate) flatten_depth: Option<usize>,
     pub(crate) millis_out_format: Vec<Item<'static>>,
     pub(crate) secs_out_format: Vec<Item<'static>>,
+    /// Formats for --compact-timestamp when the date hasn't changed
+    pub(crate) time_only_millis_out_format: Vec<Item<'static>>,
+    pub(crate) time_only_secs_out_format: Vec<Item<'static>>,
 }
 
+/// The numeric levels of pino and bunyan
+const DEFAULT_LEVEL_MAP: [(i64, Level); 6] = [
+    (10, Level::Trace),
+    (20, Level::Debug),
+    (30, Level::Info),
+    (40, Level::Warn),
+    (50, Level::Error),
+    (60, Level::Critical),
+];
+
 impl Config {
+    /// The level for a numeric level, from --level-map
+    pub(crate) fn numeric_level(&self, level: &serde_json::Number) -> Option<Level> {
+        let level = level.as_i64()?;
+        self.level_map
+            .iter()
+            .rev()
+            .find(|(number, _)| *number == level)
+            .map(|(_, name)| *name)
+    }
+
     pub(crate) fn new(args: Args) -> Self {
+        let zone = args.timezone.format_suffix();
+        let (millis_out_format, secs_out_format) = match (&args.ts_out_format, args.subsecond) {
+            (Some(format), _) => {
+                let format = parse_time_format(format).expect("checked by parse_ts_out_format");
+                (format.clone(), format)
+            }
+            (None, Some(digits)) => (
+                subsecond_out_format(digits, zone),
+                subsecond_out_format(digits, zone),
+            ),
+            (None, None) => (
+                default_millis_out_format(zone),
+                default_secs_out_format(zone),
+            ),
+        };
         Self {
             no_key_fields: args.no_key_fields,
             color: args.color,
             timestamp_format: args.timestamp_format,
             timestamp_field: args.timestamp_field,
+            level_path: FieldPath::from_field(&args.level_field),
             level_field: args.level_field,
-            millis_out_format: default_millis_out_format(),
-            secs_out_format: default_secs_out_format(),
+            output_format: args.output_format,
+            output: args.output,
+            color_max_depth: args.color_max_depth,
+            message_pointer: args.message_pointer,
+            summarize_arrays: args.summarize_arrays,
+            no_key_fields_only_if_first: args.no_key_fields_only_if_first,
+            component_field: args.component_field,
+            expand_scientific: args.expand_scientific,
+            errors_to: args.errors_to,
+            line_numbers: args.line_numbers,
+            highlight_new_errors: args.highlight_new_errors,
+            quote_style: args.quote_style,
+            trim_keys: args.trim_keys,
+            auto_detect: args.auto_detect,
+            hash_color_fields: args.hash_color_field,
+            spacing: args.spacing,
+            compact_timestamp: args.compact_timestamp,
+            auto_humanize: args.auto_humanize,
+            stream_render: args.stream_render,
+            color_test: args.color_test,
+            sort_by_value: args.sort_by_value,
+            separator: args.separator,
+            split_by_level: args.split_by_level,
+            start_after: args.start_after,
+            stop_after: args.stop_after,
+            head: args.head,
+            tail: args.tail,
+            grep: args.grep,
+            min_level: args.min_level.map(MinLevel::level),
+            invert: args.invert,
+            max_depth: args.max_depth,
+            prefix: args.prefix,
+            lossy: args.lossy,
+            timestamp_position: args.timestamp_position,
+            keep_raw: args.keep_raw,
+            warn_type_mismatch: args.warn_type_mismatch,
+            quote_header: args.quote_header,
+            lock_timestamp_unit: args.lock_timestamp_unit,
+            filter: args.filter,
+            max_fields_shown: args.max_fields_shown,
+            render_timeout: args.render_timeout,
+            empty_placeholder: args.empty_placeholder,
+            combine_consecutive_json: args.combine_consecutive_json,
+            gradients: args.gradient,
+            warn_duplicate_keys: args.warn_duplicate_keys,
+            passthrough_prefix: args.passthrough_prefix,
+            field_defaults: args.field_default,
+            labels: args.label,
+            error_format: args.error_format,
+            pairs_fields: args.pairs_field,
+            dim_when: args.dim_when,
+            count_lines: args.count_lines,
+            header_order: args.header_order,
+            max_value_bytes: args.max_value_bytes,
+            show_changes_for: args.show_changes_for,
+            template: args.template,
+            no_blank_lines: args.no_blank_lines,
+            detect_and_skip_ansi_lines: args.detect_and_skip_ansi_lines,
+            hex_fields: args.hex_field,
+            decode_base64: args.decode_base64,
+            unflatten_dotted_output: args.unflatten_dotted_output,
+            sort_keys: args.sort_keys,
+            sort_nested_keys: args.sort_nested_keys,
+            color_null: args.color_null.or(args
+                .color_by_type
+                .then_some(NamedStyle::Color(AnsiColors::BrightBlack))),
+            color_bool: args.color_bool.or(args.color_by_type.then_some((
+                NamedStyle::Color(AnsiColors::Magenta),
+                NamedStyle::Color(AnsiColors::Magenta),
+            ))),
+            lookups: args.lookup,
+            trace_fields: args.trace_field,
+            hide_info_level: args.hide_info_level,
+            inline_fields: args.inline,
+            bool_as_flag: args.bool_as_flag,
+            show_sizes: args.show_sizes,
+            exclude_fields: args.exclude_fields,
+            discover_schema: args.discover_schema,
+            only_fields: args.only_fields,
+            limit: args.limit,
+            timezone: args.timezone,
+            color_by_type: args.color_by_type,
+            require: args.require.into_iter().map(Field::new).collect(),
+            pager: args.pager,
+            watch: args.watch.map(Field::new),
+            shorten_paths: args.shorten_paths,
+            message_fields: args.message_fields,
+            max_value_width: args.max_value_width,
+            dual_timestamp: args.dual_timestamp,
+            level_map: DEFAULT_LEVEL_MAP
+                .iter()
+                .copied()
+                .chain(args.level_map)
+                .collect(),
+            multiline: args.multiline,
+            flatten_depth: args.flatten_depth,
+            millis_out_format,
+            secs_out_format,
+            time_only_millis_out_format: time_only_out_format(args.subsecond.unwrap_or(3)),
+            time_only_secs_out_format: time_only_out_format(args.subsecond.unwrap_or(0)),
         }
     }
 }
 
-pub(crate) fn default_millis_out_format() -> Vec<Item<'static>> {
-    StrftimeItems::new("%Y-%m-%dT%H:%M:%S.%3fZ")
+/// The default formats end in `zone`, see [`Timezone::format_suffix`]
+pub(crate) fn default_millis_out_format(zone: &str) -> Vec<Item<'static>> {
+    parse_time_format(&format!("%Y-%m-%dT%H:%M:%S.%3f{zone}")).unwrap()
+}
+pub(crate) fn default_secs_out_format(zone: &str) -> Vec<Item<'static>> {
+    parse_time_format(&format!("%Y-%m-%dT%H:%M:%S{zone}")).unwrap()
+}
+
+/// The default format with `digits` of sub-second precision
+fn subsecond_out_format(digits: u8, zone: &str) -> Vec<Item<'static>> {
+    if digits == 0 {
+        default_secs_out_format(zone)
+    } else {
+        parse_time_format(&format!("%Y-%m-%dT%H:%M:%S.%{digits}f{zone}")).unwrap()
+    }
+}
+
+/// A time without a date, with `digits` of sub-second precision
+fn time_only_out_format(digits: u8) -> Vec<Item<'static>> {
+    if digits == 0 {
+        parse_time_format("%H:%M:%S").unwrap()
+    } else {
+        parse_time_format(&format!("%H:%M:%S.%{digits}f")).unwrap()
+    }
+}
+
+fn parse_level(level: &str) -> Result<Level, String> {
+    Level::parse(level).ok_or_else(|| format!("unknown level {level:?}"))
+}
+
+fn parse_style(style: &str) -> Result<NamedStyle, String> {
+    NamedStyle::parse(style).ok_or_else(|| format!("unknown color {style:?}"))
+}
+
+/// Parse `true-color,false-color`
+fn parse_bool_styles(styles: &str) -> Result<(NamedStyle, NamedStyle), String> {
+    let Some((true_style, false_style)) = styles.split_once(',') else {
+        return Err(format!("expected TRUE,FALSE colors but got {styles:?}"));
+    };
+    Ok((parse_style(true_style)?, parse_style(false_style)?))
+}
+
+fn parse_millis(millis: &str) -> Result<Duration, String> {
+    millis
+        .parse()
+        .map(Duration::from_millis)
+        .map_err(|e| format!("invalid milliseconds: {e}"))
+}
+
+fn parse_subsecond(digits: &str) -> Result<u8, String> {
+    match digits {
+        "0" | "3" | "6" | "9" => Ok(digits.parse().unwrap()),
+        _ => Err("must be one of 0, 3, 6 or 9".to_string()),
+    }
+}
+
+/// Check that a --ts-out-format is valid, it is parsed in `Config::new`
+fn parse_ts_out_format(format: &str) -> Result<String, String> {
+    parse_time_format(format)?;
+    Ok(format.to_string())
+}
+
+/// Parse `number=level`, for --level-map
+fn parse_level_mapping(mapping: &str) -> Result<(i64, Level), String> {
+    let (number, level) = parse_key_value(mapping)?;
+    let number = number
         .parse()
-        .unwrap()
+        .map_err(|e| format!("invalid level number {number:?}: {e}"))?;
+    Ok((number, parse_level(&level)?))
 }
-pub(crate) fn default_secs_out_format() -> Vec<Item<'static>> {
-    StrftimeItems::new("%Y-%m-%dT%H:%M:%SZ").parse().unwrap()
+
+/// Parse `key=value`, splitting at the first `=`
+fn parse_key_value(pair: &str) -> Result<(String, String), String> {
+    match pair.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE but got {pair:?}")),
+    }
+}
+
+/// Interpret `\t` and `\\` escapes, so that a tab can be passed on the command
+/// line
+fn parse_separator(separator: &str) -> Result<String, String> {
+    let mut parsed = String::with_capacity(separator.len());
+    let mut chars = separator.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            parsed.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => parsed.push('\t'),
+            Some('\\') => parsed.push('\\'),
+            Some(other) => return Err(format!("unknown escape \\{other}")),
+            None => return Err("trailing \\".to_string()),
+        }
+    }
+    Ok(parsed)
+}
+
+/// Compile a strftime format string
+///
+/// Timestamp formats must be compiled once at startup and stored in the
+/// `Config`, formatting a timestamp should never need to re-parse its format.
+pub(crate) fn parse_time_format(format: &str) -> Result<Vec<Item<'static>>, String> {
+    StrftimeItems::new(format)
+        .parse_to_owned()
+        .map_err(|e| format!("invalid time format {format:?}: {e}"))
+}
+
+impl Args {
+    /// Check the combinations of arguments that clap can't
+    pub(crate) fn check(&self) -> Result<(), clap::Error> {
+        if self.stream_render && self.output_format == OutputFormat::Json {
+            return Err(Args::command().error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--stream-render can't be used with --output-format=json",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A numeric field colored by where its value falls between `low` and `high`
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Gradient {
+    pub(crate) field: String,
+    low: f64,
+    high: f64,
+}
+
+impl Gradient {
+    /// Parse `field:low:high`
+    fn parse(gradient: &str) -> Result<Self, String> {
+        let mut parts = gradient.rsplitn(3, ':');
+        let (Some(high), Some(low), Some(field)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err("must be field:low:high".to_string());
+        };
+        let number = |n: &str| n.parse().map_err(|e| format!("invalid number {n:?}: {e}"));
+        let (low, high) = (number(low)?, number(high)?);
+        if low >= high {
+            return Err("low must be less than high".to_string());
+        }
+        Ok(Self {
+            field: field.to_string(),
+            low,
+            high,
+        })
+    }
+
+    /// Where `value` is between low, 0, and high, 1
+    pub(crate) fn position(&self, value: f64) -> f64 {
+        ((value - self.low) / (self.high - self.low)).clamp(0.0, 1.0)
+    }
+}
+
+/// A table to translate the values of a field through
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Lookup {
+    pub(crate) field: String,
+    table: Vec<(String, String)>,
+}
+
+impl Lookup {
+    /// Parse `field:value=text,value=text`
+    fn parse(lookup: &str) -> Result<Self, String> {
+        let Some((field, table)) = lookup.split_once(':') else {
+            return Err("must be field:value=text,...".to_string());
+        };
+        let table = table
+            .split(',')
+            .map(parse_key_value)
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            field: field.to_string(),
+            table,
+        })
+    }
+
+    /// The text for `value`, if it is in the table
+    pub(crate) fn get(&self, value: &str) -> Option<&str> {
+        self.table
+            .iter()
+            .find(|(key, _)| key == value)
+            .map(|(_, text)| text.as_str())
+    }
+}
+
+/// The timezone timestamps are shown in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Timezone {
+    Utc,
+    Local,
+    Named(chrono_tz::Tz),
+}
+
+impl Timezone {
+    fn parse(zone: &str) -> Result<Self, String> {
+        if zone.eq_ignore_ascii_case("utc") {
+            Ok(Timezone::Utc)
+        } else if zone.eq_ignore_ascii_case("local") {
+            Ok(Timezone::Local)
+        } else {
+            zone.parse()
+                .map(Timezone::Named)
+                .map_err(|_| format!("unknown timezone {zone:?}"))
+        }
+    }
+
+    /// How the default formats end: `Z` in UTC, and the offset otherwise
+    fn format_suffix(self) -> &'static str {
+        match self {
+            Timezone::Utc => "Z",
+            Timezone::Local | Timezone::Named(_) => "%:z",
+        }
+    }
+
+    pub(crate) fn convert(self, datetime: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self {
+            Timezone::Utc => datetime.fixed_offset(),
+            Timezone::Local => datetime.with_timezone(&Local).fixed_offset(),
+            Timezone::Named(zone) => datetime.with_timezone(&zone).fixed_offset(),
+        }
+    }
 }
 
 #[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
@@ -78,10 +1126,82 @@ pub(crate) enum ColorOption {
     Never,
 }
 
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum MinLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Crit,
+}
+
+impl MinLevel {
+    fn level(self) -> Level {
+        match self {
+            MinLevel::Trace => Level::Trace,
+            MinLevel::Debug => Level::Debug,
+            MinLevel::Info => Level::Info,
+            MinLevel::Warn => Level::Warn,
+            MinLevel::Error => Level::Error,
+            MinLevel::Crit => Level::Critical,
+        }
+    }
+}
+
 #[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
 pub(crate) enum TimestampFormat {
     Auto,
     Seconds,
     Millis,
+    Nanos,
+    Uptime,
     Raw,
 }
+
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Position {
+    Front,
+    End,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum HeaderOrder {
+    Config,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum ErrorFormat {
+    Text,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum OutputFormat {
+    Text,
+    Html,
+    AnsiToFile,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum QuoteStyle {
+    Double,
+    Single,
+}
+
+impl QuoteStyle {
+    pub(crate) fn quote(self) -> char {
+        match self {
+            QuoteStyle::Double => '"',
+            QuoteStyle::Single => '\'',
+        }
+    }
+}
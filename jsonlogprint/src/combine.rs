@@ -0,0 +1,134 @@
+use std::io;
+
+/// The most lines an object is joined from with --multiline
+pub(crate) const MAX_MULTILINE_LINES: usize = 1000;
+
+/// Joins JSON objects that are spread over several lines, like pretty printed
+/// JSON, into a single line by counting braces
+///
+/// A line starting with `{` is joined with the lines after it until all of its
+/// braces and brackets have been closed. Other lines are passed through.
+///
+/// Each line comes with the 1-based number of the first input line it was
+/// joined from.
+pub(crate) struct CombineBraces<I> {
+    lines: I,
+    /// How many input lines have been read
+    read: usize,
+    enabled: bool,
+    /// Stop joining at a blank line, a line starting with `{` or after
+    /// `MAX_MULTILINE_LINES`, for --multiline
+    ///
+    /// The lines joined so far aren't a valid object, so they are shown as
+    /// they are.
+    limited: bool,
+    /// A blank line or error read while combining, returned after the
+    /// combined line
+    pending: Option<(usize, io::Result<String>)>,
+    /// A line starting with `{` that stopped the previous object, which may
+    /// start the next one
+    restart: Option<(usize, String)>,
+}
+
+impl<I> CombineBraces<I> {
+    pub(crate) fn new(lines: I, enabled: bool, limited: bool) -> Self {
+        Self {
+            lines,
+            read: 0,
+            enabled,
+            limited,
+            pending: None,
+            restart: None,
+        }
+    }
+}
+
+impl<I: Iterator<Item = io::Result<String>>> CombineBraces<I> {
+    /// The next input line, with its number
+    fn next_line(&mut self) -> Option<(usize, io::Result<String>)> {
+        let line = self.lines.next()?;
+        self.read += 1;
+        Some((self.read, line))
+    }
+}
+
+impl<I: Iterator<Item = io::Result<String>>> Iterator for CombineBraces<I> {
+    type Item = (usize, io::Result<String>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(line) = self.pending.take() {
+            return Some(line);
+        }
+        let (number, line) = match self.restart.take() {
+            Some((number, line)) => (number, Ok(line)),
+            None => self.next_line()?,
+        };
+        let mut combined = match line {
+            Ok(line) if self.enabled && line.starts_with('{') => line,
+            line => return Some((number, line)),
+        };
+        let mut depth = BraceDepth::default();
+        depth.update(&combined);
+        let mut joined = 1;
+        while depth.is_open() && !(self.limited && joined >= MAX_MULTILINE_LINES) {
+            match self.next_line() {
+                Some((next, Ok(line))) if self.limited && line.starts_with('{') => {
+                    self.restart = Some((next, line));
+                    break;
+                }
+                Some((next, Ok(line))) if self.limited && line.trim().is_empty() => {
+                    self.pending = Some((next, Ok(line)));
+                    break;
+                }
+                Some((_, Ok(line))) => {
+                    depth.update(&line);
+                    combined.push('\n');
+                    combined.push_str(&line);
+                    joined += 1;
+                }
+                Some((next, Err(e))) => {
+                    self.pending = Some((next, Err(e)));
+                    break;
+                }
+                None => break,
+            }
+        }
+        Some((number, Ok(combined)))
+    }
+}
+
+/// How deeply nested the end of the text seen so far is, ignoring braces in
+/// strings
+#[derive(Default)]
+struct BraceDepth {
+    depth: i64,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl BraceDepth {
+    fn update(&mut self, line: &str) {
+        for byte in line.bytes() {
+            if self.escaped {
+                self.escaped = false;
+            } else if self.in_string {
+                match byte {
+                    b'\\' => self.escaped = true,
+                    b'"' => self.in_string = false,
+                    _ => {}
+                }
+            } else {
+                match byte {
+                    b'"' => self.in_string = true,
+                    b'{' | b'[' => self.depth += 1,
+                    b'}' | b']' => self.depth -= 1,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.depth > 0
+    }
+}
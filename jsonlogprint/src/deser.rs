@@ -1,10 +1,12 @@
 use std::borrow::Cow;
 use std::fmt;
+use std::io;
 
 use serde::de::DeserializeSeed;
 use serde::de::Visitor;
 use serde::Deserialize;
 use serde::Serialize;
+use tracing::warn;
 
 use crate::FnvIndexMap;
 
@@ -23,9 +25,28 @@ pub(crate) enum JsonValue<'a> {
     Removed,
 }
 
+impl JsonValue<'_> {
+    /// The name of the JSON type of this value, for diagnostics
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            JsonValue::String(_) => "string",
+            JsonValue::Number(_) => "number",
+            JsonValue::Bool(_) => "bool",
+            JsonValue::Null => "null",
+            JsonValue::Object(_) => "object",
+            JsonValue::Array(_) => "array",
+            JsonValue::Removed => "removed",
+        }
+    }
+}
+
 // Custom DeserializeSeed and Visitor
 pub(crate) struct IndexMapSeed<'a, 'b> {
     pub(crate) map: &'b mut FnvIndexMap<&'a str, JsonValue<'a>>,
+    /// Strip whitespace from the top-level keys
+    pub(crate) trim_keys: bool,
+    /// Warn that duplicate keys on this line are overwritten, if set
+    pub(crate) warn_duplicates_on_line: Option<usize>,
 }
 
 impl<'de, 'a, 'b> DeserializeSeed<'de> for IndexMapSeed<'a, 'b>
@@ -57,7 +78,55 @@ where
         M: serde::de::MapAccess<'de>,
     {
         while let Some((key, value)) = access.next_entry::<&'a str, JsonValue<'a>>()? {
-            self.map.insert(key, value);
+            let key = if self.trim_keys { key.trim() } else { key };
+            let replaced = self.map.insert(key, value);
+            if let (Some(_), Some(line)) = (replaced, self.warn_duplicates_on_line) {
+                warn!(line, %key, "duplicate key, the earlier value was dropped");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Deserializes the top-level entries of a JSON object one at a time, passing
+/// each to `on_entry` as soon as it is parsed instead of collecting them
+pub(crate) struct EntrySeed<F> {
+    pub(crate) on_entry: F,
+    /// Strip whitespace from the top-level keys
+    pub(crate) trim_keys: bool,
+}
+
+impl<'de, F> DeserializeSeed<'de> for EntrySeed<F>
+where
+    F: FnMut(&str, JsonValue<'de>) -> io::Result<()>,
+{
+    type Value = ();
+
+    fn deserialize<D>(self, deserializer: D) -> Result<(), D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, F> Visitor<'de> for EntrySeed<F>
+where
+    F: FnMut(&str, JsonValue<'de>) -> io::Result<()>,
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a JSON object")
+    }
+
+    fn visit_map<M>(mut self, mut access: M) -> Result<(), M::Error>
+    where
+        M: serde::de::MapAccess<'de>,
+    {
+        while let Some((key, value)) = access.next_entry::<&'de str, JsonValue<'de>>()? {
+            let key = if self.trim_keys { key.trim() } else { key };
+            (self.on_entry)(key, value).map_err(serde::de::Error::custom)?;
         }
         Ok(())
     }
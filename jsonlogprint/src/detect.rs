@@ -0,0 +1,114 @@
+use tracing::debug;
+
+use crate::cfg::{Config, TimestampFormat};
+use crate::deser::JsonValue;
+use crate::styler::Level;
+use crate::FnvIndexMap;
+
+/// The number of lines buffered to detect the format from
+pub(crate) const SAMPLE_LINES: usize = 10;
+
+const TIMESTAMP_FIELDS: &[&str] = &["timestamp", "time", "ts", "@timestamp", "t"];
+const LEVEL_FIELDS: &[&str] = &["level", "lvl", "severity", "levelname", "log_level"];
+pub(crate) const MESSAGE_FIELDS: &[&str] = &["msg", "message", "text", "log", "event"];
+
+/// Update `config` with the timestamp unit, level field and message field
+/// detected from `lines`
+///
+/// Anything that can't be detected is left as configured.
+pub(crate) fn apply(lines: &[String], config: &mut Config) {
+    let records = parse_records(lines);
+    if let Some((field, format)) = detect_timestamp(&records) {
+        debug!(field, ?format, "detected timestamp");
+        config.timestamp_field = field.to_string();
+        config.timestamp_format = format;
+        add_no_key_field(config, field);
+    }
+    if let Some(field) = detect_field(&records, LEVEL_FIELDS, |level| {
+        Level::parse(level).is_some()
+    }) {
+        debug!(field, "detected level");
+        config.level_field = field.to_string();
+        add_no_key_field(config, field);
+    }
+    if let Some(field) = detect_field(&records, MESSAGE_FIELDS, |_| true) {
+        debug!(field, "detected message");
+        add_no_key_field(config, field);
+    }
+}
+
+/// Replace --timestamp-format=auto with the unit detected from `lines`, so
+/// that the unit doesn't need to be guessed for every line
+///
+/// If no timestamps are found the format is left as auto.
+pub(crate) fn lock_timestamp_unit(lines: &[String], config: &mut Config) {
+    if config.timestamp_format != TimestampFormat::Auto {
+        return;
+    }
+    let records = parse_records(lines);
+    if let Some(format) = timestamp_unit(&records, &config.timestamp_field) {
+        debug!(?format, "locked timestamp unit");
+        config.timestamp_format = format;
+    }
+}
+
+fn parse_records(lines: &[String]) -> Vec<FnvIndexMap<&str, JsonValue<'_>>> {
+    lines
+        .iter()
+        .filter(|line| line.starts_with('{'))
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+fn add_no_key_field(config: &mut Config, field: &str) {
+    if !config.no_key_fields.iter().any(|key| key == field) {
+        config.no_key_fields.push(field.to_string());
+    }
+}
+
+/// The first candidate with an integer value in any record, and the unit of
+/// the largest such value
+fn detect_timestamp(
+    records: &[FnvIndexMap<&str, JsonValue>],
+) -> Option<(&'static str, TimestampFormat)> {
+    TIMESTAMP_FIELDS
+        .iter()
+        .find_map(|field| Some((*field, timestamp_unit(records, field)?)))
+}
+
+/// The unit of the largest integer `field` in any record
+fn timestamp_unit(
+    records: &[FnvIndexMap<&str, JsonValue>],
+    field: &str,
+) -> Option<TimestampFormat> {
+    let largest = records
+        .iter()
+        .filter_map(|record| match record.get(field) {
+            Some(JsonValue::Number(num)) => num.as_i64(),
+            _ => None,
+        })
+        .map(i64::unsigned_abs)
+        .max()?;
+    if largest > crate::NANOS_THRESHOLD {
+        Some(TimestampFormat::Nanos)
+    } else if largest > crate::YEAR_3K_EPOCH {
+        Some(TimestampFormat::Millis)
+    } else {
+        Some(TimestampFormat::Seconds)
+    }
+}
+
+/// The first candidate with a string value that matches `is_valid` in any
+/// record
+fn detect_field(
+    records: &[FnvIndexMap<&str, JsonValue>],
+    candidates: &[&'static str],
+    is_valid: impl Fn(&str) -> bool,
+) -> Option<&'static str> {
+    candidates.iter().copied().find(|field| {
+        records.iter().any(|record| match record.get(field) {
+            Some(JsonValue::String(value)) => is_valid(value),
+            _ => false,
+        })
+    })
+}
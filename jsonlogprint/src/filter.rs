@@ -0,0 +1,277 @@
+use std::cmp::Ordering;
+
+use crate::deser::JsonValue;
+use crate::path::Field;
+use crate::FnvIndexMap;
+
+/// A --filter expression, like `level == "error" && status >= 500`
+///
+/// Expressions compare fields to literals with `==`, `!=`, `<`, `<=`, `>` and
+/// `>=`, and are combined with `&&`, `||`, `!` and parentheses. A field on its
+/// own matches if it exists and isn't `false` or `null`.
+#[derive(Debug, Clone)]
+pub(crate) enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Truthy(Field),
+    Compare(Field, Op, Literal),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Literal {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Null,
+}
+
+impl Filter {
+    pub(crate) fn parse(expression: &str) -> Result<Self, String> {
+        let tokens = tokenize(expression)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let filter = parser.or()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(filter),
+            Some(token) => Err(format!("unexpected {token:?} in filter")),
+        }
+    }
+
+    pub(crate) fn matches(&self, record: &FnvIndexMap<&str, JsonValue>) -> bool {
+        match self {
+            Filter::And(a, b) => a.matches(record) && b.matches(record),
+            Filter::Or(a, b) => a.matches(record) || b.matches(record),
+            Filter::Not(filter) => !filter.matches(record),
+            Filter::Truthy(field) => !matches!(
+                field.get(record),
+                None | Some(JsonValue::Null | JsonValue::Bool(false))
+            ),
+            // Missing fields and values of a different type are unequal to
+            // everything, and neither less nor greater than anything
+            Filter::Compare(field, op, literal) => {
+                match field.get(record).and_then(|value| compare(value, literal)) {
+                    Some(ordering) => op.accepts(ordering),
+                    None => *op == Op::Ne,
+                }
+            }
+        }
+    }
+}
+
+impl Op {
+    fn accepts(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering.is_eq(),
+            Op::Ne => ordering.is_ne(),
+            Op::Lt => ordering.is_lt(),
+            Op::Le => ordering.is_le(),
+            Op::Gt => ordering.is_gt(),
+            Op::Ge => ordering.is_ge(),
+        }
+    }
+}
+
+fn compare(value: &JsonValue, literal: &Literal) -> Option<Ordering> {
+    match (value, literal) {
+        (JsonValue::String(value), Literal::String(literal)) => Some((**value).cmp(literal)),
+        (JsonValue::Number(value), Literal::Number(literal)) => {
+            value.as_f64()?.partial_cmp(literal)
+        }
+        (JsonValue::Bool(value), Literal::Bool(literal)) => Some(value.cmp(literal)),
+        (JsonValue::Null, Literal::Null) => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Literal(Literal),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || matches!(c, '_' | '@' | '/')
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '@' | '/' | '.' | '-' | '~')
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let mut next_is = |expected: char| chars.next_if(|(_, c)| *c == expected).is_some();
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '&' if next_is('&') => Token::And,
+            '|' if next_is('|') => Token::Or,
+            '=' if next_is('=') => Token::Op(Op::Eq),
+            '!' if next_is('=') => Token::Op(Op::Ne),
+            '!' => Token::Not,
+            '<' if next_is('=') => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if next_is('=') => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '"' | '\'' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, escaped)) => string.push(escaped),
+                            None => return Err("unterminated string in filter".to_string()),
+                        },
+                        Some((_, end)) if end == c => break,
+                        Some((_, other)) => string.push(other),
+                        None => return Err("unterminated string in filter".to_string()),
+                    }
+                }
+                Token::Literal(Literal::String(string))
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut end = start + c.len_utf8();
+                while let Some((index, c)) = chars
+                    .next_if(|(_, c)| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+'))
+                {
+                    end = index + c.len_utf8();
+                }
+                let number = &expression[start..end];
+                match number.parse() {
+                    Ok(number) => Token::Literal(Literal::Number(number)),
+                    Err(_) => return Err(format!("invalid number {number:?} in filter")),
+                }
+            }
+            c if is_ident_start(c) => {
+                let mut end = start + c.len_utf8();
+                while let Some((index, c)) = chars.next_if(|(_, c)| is_ident(*c)) {
+                    end = index + c.len_utf8();
+                }
+                match &expression[start..end] {
+                    "true" => Token::Literal(Literal::Bool(true)),
+                    "false" => Token::Literal(Literal::Bool(false)),
+                    "null" => Token::Literal(Literal::Null),
+                    ident => Token::Ident(ident.to_string()),
+                }
+            }
+            c => return Err(format!("unexpected {c:?} in filter")),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// A recursive descent parser, from lowest to highest precedence:
+///
+/// ```text
+/// or         = and ("||" and)*
+/// and        = unary ("&&" unary)*
+/// unary      = "!" unary | "(" or ")" | comparison
+/// comparison = field (op literal)?
+/// ```
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn next_if(&mut self, expected: &Token) -> bool {
+        let matches = self.tokens.get(self.position) == Some(expected);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    fn or(&mut self) -> Result<Filter, String> {
+        let mut filter = self.and()?;
+        while self.next_if(&Token::Or) {
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, String> {
+        let mut filter = self.unary()?;
+        while self.next_if(&Token::And) {
+            filter = Filter::And(Box::new(filter), Box::new(self.unary()?));
+        }
+        Ok(filter)
+    }
+
+    fn unary(&mut self) -> Result<Filter, String> {
+        match self.next() {
+            Some(Token::Not) => Ok(Filter::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let filter = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(filter),
+                    _ => Err("missing ')' in filter".to_string()),
+                }
+            }
+            Some(Token::Ident(name)) => {
+                let field = Field::new(name);
+                let Some(Token::Op(op)) = self.tokens.get(self.position).cloned() else {
+                    return Ok(Filter::Truthy(field));
+                };
+                self.position += 1;
+                match self.next() {
+                    Some(Token::Literal(literal)) => Ok(Filter::Compare(field, op, literal)),
+                    Some(token) => Err(format!("expected a value but found {token:?} in filter")),
+                    None => Err("expected a value at the end of the filter".to_string()),
+                }
+            }
+            Some(token) => Err(format!("expected a field but found {token:?} in filter")),
+            None => Err("expected a field at the end of the filter".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_precedence() {
+        let filter = Filter::parse(r#"a == 1 || b && !(c != "x")"#).unwrap();
+        let Filter::Or(a, b) = filter else {
+            panic!("expected || at the top: {filter:?}");
+        };
+        assert!(matches!(*a, Filter::Compare(_, Op::Eq, Literal::Number(n)) if n == 1.0));
+        assert!(matches!(*b, Filter::And(_, ref not) if matches!(**not, Filter::Not(_))));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Filter::parse("level ==").is_err());
+        assert!(Filter::parse("(a").is_err());
+        assert!(Filter::parse(r#"msg == "open"#).is_err());
+        assert!(Filter::parse("a b").is_err());
+    }
+}
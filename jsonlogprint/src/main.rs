@@ -1,31 +1,59 @@
-use chrono::format::Item;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use clap::Parser;
 use fnv::FnvBuildHasher;
 use indexmap::IndexMap;
+use owo_colors::AnsiColors;
 use serde::de::DeserializeSeed as _;
-use std::io::{self, BufRead, BufWriter, Write};
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, IsTerminal as _, Write};
+use std::process::Child;
+use std::time::Instant;
 use tracing::{debug, trace, warn};
 use tracing_subscriber::{self, EnvFilter};
 
 use deser::JsonValue;
 
-use self::styler::Styler;
+use self::output::OutputSink;
+use self::styler::{Level, NamedStyle, Styler};
 
+mod base64;
 mod cfg;
+mod combine;
 mod deser;
+mod detect;
+mod filter;
+mod output;
+mod pager;
+mod path;
+mod schema;
 mod styler;
+mod template;
 
 /// The number of seconds between 1970 and 3000
 ///
 /// If timestamp_format = auto we use this to determine if we should convert
-/// using millis or seconds.
-const YEAR_3K_EPOCH: i64 = 32503698000;
+/// using millis or seconds. Negative timestamps are compared by magnitude, so
+/// that pre-1970 millis are detected as well.
+pub(crate) const YEAR_3K_EPOCH: u64 = 32503698000;
+
+/// Timestamps larger than this are nanoseconds if timestamp_format = auto
+///
+/// 1e17 nanoseconds is in 1973, and 1e17 milliseconds is millions of years
+/// after 3000.
+pub(crate) const NANOS_THRESHOLD: u64 = 100_000_000_000_000_000;
 
 type FnvIndexMap<K, V> = IndexMap<K, V, FnvBuildHasher>;
+type FnvHashSet<T> = std::collections::HashSet<T, FnvBuildHasher>;
+type FnvHashMap<K, V> = std::collections::HashMap<K, V, FnvBuildHasher>;
 
 fn main() {
     let args = cfg::Args::parse();
+    if let Err(e) = args.check() {
+        e.exit();
+    }
     let config = cfg::Config::new(args);
 
     init_logging();
@@ -33,10 +61,94 @@ fn main() {
 
     let stdin = io::stdin();
     let handle = stdin.lock();
-    let stdout = io::stdout();
-    let handle_out = BufWriter::with_capacity(32 * 1024, stdout.lock());
+    let (handle_out, pager) = match open_output(&config) {
+        Ok(out) => out,
+        Err(e) => {
+            eprintln!("error: unable to open output: {e}");
+            std::process::exit(1);
+        }
+    };
+    if config.color_test {
+        let sample = color_test_input(&config);
+        transform_lines(io::Cursor::new(sample), handle_out, config);
+    } else {
+        let sink = match (&config.split_by_level, config.errors_to) {
+            (Some(dir), _) => {
+                drop(handle_out);
+                OutputSink::split_by_level(dir.clone())
+            }
+            (None, None) => OutputSink::new(handle_out),
+            (None, Some(cfg::OutputStream::Stdout)) => {
+                OutputSink::with_errors(io::stderr(), handle_out)
+            }
+            (None, Some(cfg::OutputStream::Stderr)) => {
+                OutputSink::with_errors(handle_out, io::stderr())
+            }
+        };
+
+        transform_lines(handle, sink, config);
+    }
+    if let Some(mut pager) = pager {
+        // The output is shown until the user quits the pager
+        pager.wait().ok();
+    }
+}
+
+type Output = BufWriter<Box<dyn Write>>;
 
-    transform_lines(handle, handle_out, config);
+/// The output, and the --pager showing it if there is one
+fn open_output(config: &cfg::Config) -> io::Result<(Output, Option<Child>)> {
+    let mut pager = None;
+    let out: Box<dyn Write> = match &config.output {
+        Some(path) => Box::new(File::create(path)?),
+        // With --split-by-level nothing is written here, so there's nothing
+        // to page
+        None if config.pager && config.split_by_level.is_none() && io::stdout().is_terminal() => {
+            let command = std::env::var("PAGER");
+            let (child, input) = pager::spawn(command.as_deref().unwrap_or(pager::DEFAULT_PAGER))?;
+            pager = Some(child);
+            Box::new(input)
+        }
+        None => Box::new(io::stdout().lock()),
+    };
+    Ok((BufWriter::with_capacity(32 * 1024, out), pager))
+}
+
+/// Sample records showing each level and the color of each nesting depth,
+/// for --color-test
+fn color_test_input(config: &cfg::Config) -> String {
+    use serde_json::{Map, Value};
+
+    let message_field = config
+        .no_key_fields
+        .iter()
+        .find(|key| detect::MESSAGE_FIELDS.contains(&key.as_str()))
+        .map_or("msg", String::as_str);
+    let record = |level: &str, message: String| {
+        let mut record = Map::new();
+        record.insert(config.timestamp_field.clone(), 1627494000.into());
+        record.insert(config.level_field.clone(), level.into());
+        record.insert(message_field.to_string(), message.into());
+        record
+    };
+
+    let mut lines = String::new();
+    for level in ["trace", "debug", "info", "warn", "error", "critical"] {
+        let record = record(level, format!("a sample {level} message"));
+        lines.push_str(&Value::Object(record).to_string());
+        lines.push('\n');
+    }
+    let mut nested = Value::from("deepest");
+    for depth in (1..=7).rev() {
+        let mut parent = Map::new();
+        parent.insert(format!("depth{depth}"), nested);
+        nested = Value::Object(parent);
+    }
+    let mut record = record("info", "nested depth colors".to_string());
+    record.insert("depth0".to_string(), nested);
+    lines.push_str(&Value::Object(record).to_string());
+    lines.push('\n');
+    lines
 }
 
 fn init_logging() {
@@ -60,49 +172,322 @@ fn init_logging() {
     });
 }
 
+/// State that is carried from one line to the next
+#[derive(Default)]
+struct StreamState {
+    /// Messages of the error records seen so far, for --highlight-new-errors
+    seen_errors: FnvHashSet<String>,
+    /// The date of the previous timestamp, for --compact-timestamp
+    previous_date: Option<NaiveDate>,
+    /// The number of the input line the current record starts on
+    line_number: usize,
+    /// The kinds of lines that were shown, for --count-lines
+    counts: LineCounts,
+    /// The last value of each --show-changes-for field
+    previous_values: FnvHashMap<String, serde_json::Value>,
+    /// The fields seen so far, for --discover-schema
+    schema: schema::Schema,
+    /// The value of the --watch field in the last record that was shown
+    watched: Option<serde_json::Value>,
+}
+
+/// How many lines of each kind were shown
+#[derive(Default)]
+struct LineCounts {
+    records: usize,
+    passthrough: usize,
+    errors: usize,
+}
+
+impl fmt::Display for LineCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "records={} passthrough={} errors={}",
+            self.records, self.passthrough, self.errors
+        )
+    }
+}
+
 struct Reusable<'a> {
     map: FnvIndexMap<&'a str, JsonValue<'a>>,
     newline_fields: Vec<usize>,
+    /// Indices into --no-key-fields in the order they are printed
+    header_order: Vec<usize>,
+    /// The rendered timestamp, for --timestamp-position=end
+    trailing: Vec<u8>,
+    /// Arrays in --show-changes-for fields that changed since the previous
+    /// record
+    array_diffs: Vec<ArrayDiff<'a>>,
 }
 
-fn transform_lines(handle: impl BufRead, mut out: impl Write, config: cfg::Config) {
+impl Reusable<'_> {
+    /// Forget everything about the current record, keeping the allocations
+    ///
+    /// This must run after every line, the map borrows from the line that is
+    /// dropped once `process_line` returns.
+    fn reset(&mut self) {
+        self.map.clear();
+        self.newline_fields.clear();
+        self.header_order.clear();
+        self.trailing.clear();
+        self.array_diffs.clear();
+    }
+}
+
+/// The elements of an array compared to the previous value of its field
+struct ArrayDiff<'a> {
+    field: &'a str,
+    /// The current elements, followed by the removed ones
+    elements: Vec<(serde_json::Value, Change)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Same,
+    Added,
+    Removed,
+}
+
+impl<'a> ArrayDiff<'a> {
+    fn new(field: &'a str, previous: &[serde_json::Value], current: &[serde_json::Value]) -> Self {
+        let added = current.iter().map(|element| {
+            let change = if previous.contains(element) {
+                Change::Same
+            } else {
+                Change::Added
+            };
+            (element.clone(), change)
+        });
+        let removed = previous
+            .iter()
+            .filter(|element| !current.contains(element))
+            .map(|element| (element.clone(), Change::Removed));
+        Self {
+            field,
+            elements: added.chain(removed).collect(),
+        }
+    }
+}
+
+fn transform_lines<'o>(
+    handle: impl BufRead,
+    sink: impl Into<OutputSink<'o>>,
+    mut config: cfg::Config,
+) {
+    let mut sink = sink.into();
+    let lossy = config.lossy;
+    let lines = handle
+        .split(b'\n')
+        .map(|line| line.and_then(|line| decode_line(line, lossy)));
+    let mut lines = combine::CombineBraces::new(
+        lines,
+        config.combine_consecutive_json || config.multiline,
+        config.multiline,
+    );
+    let mut sampled = Vec::new();
+    if config.auto_detect || config.lock_timestamp_unit {
+        sampled.extend(lines.by_ref().take(detect::SAMPLE_LINES));
+        let sample: Vec<String> = sampled
+            .iter()
+            .filter_map(|(_, line)| line.as_ref().ok().cloned())
+            .collect();
+        if config.auto_detect {
+            detect::apply(&sample, &mut config);
+            debug!(config = ?config, "auto-detected format");
+        }
+        if config.lock_timestamp_unit {
+            detect::lock_timestamp_unit(&sample, &mut config);
+        }
+    }
+
     // Reuse the same map for each line
     let mut reusable = Reusable {
         map: FnvIndexMap::with_capacity_and_hasher(24, FnvBuildHasher::default()),
         newline_fields: Vec::with_capacity(config.no_key_fields.len()),
+        header_order: Vec::with_capacity(config.no_key_fields.len()),
+        trailing: Vec::new(),
+        array_diffs: Vec::new(),
     };
-    let styler = Styler::new(config.color);
+    // Each line is rendered here before being written to the sink
+    let mut rendered = Vec::with_capacity(4 * 1024);
+    let mut state = StreamState::default();
+    let styler = Styler::new(&config);
+    // The styled --prefix, and a buffer to add it to each line of a record
+    let prefix = config
+        .prefix
+        .as_ref()
+        .map(|prefix| format!("{} ", styler.hashed(prefix, prefix)));
+    let mut prefixed = Vec::new();
 
-    for line in handle.lines() {
-        match line {
-            Ok(json_line) => {
-                process_line(json_line, &mut reusable, &mut out, &config, styler);
-                out.flush().unwrap();
-            }
+    sink.write_all_streams(styler.document_start()).unwrap();
+    // Whether a --start-after marker has been seen
+    let mut started = config.start_after.is_none();
+    let head_reached = |written| config.head.is_some_and(|head| written >= head);
+    let limit_reached = |records| config.limit.is_some_and(|limit| records >= limit);
+    let mut written = 0;
+    // The last --tail records, with their levels
+    let mut tail = VecDeque::with_capacity(config.tail.unwrap_or_default());
+    for (line_number, line) in sampled.into_iter().chain(lines) {
+        if head_reached(written) || limit_reached(state.counts.records) {
+            break;
+        }
+        let raw = line.as_deref().unwrap_or_default();
+        if !started {
+            started = config
+                .start_after
+                .as_ref()
+                .is_some_and(|re| re.is_match(raw));
+            continue;
+        }
+        let stop = config
+            .stop_after
+            .as_ref()
+            .is_some_and(|re| re.is_match(raw));
+        state.line_number = line_number;
+        if config.line_numbers {
+            write!(
+                rendered,
+                "{} ",
+                styler.line_number(&LineNumber(line_number))
+            )
+            .unwrap();
+        }
+        let content_start = rendered.len();
+        let outcome = match line {
+            Ok(json_line) => process_line(
+                json_line,
+                &mut reusable,
+                &mut state,
+                &mut rendered,
+                &config,
+                styler,
+            ),
             Err(e) => {
                 warn!("Failed to read line from stdin: {}", e);
-                writeln!(out).unwrap();
+                writeln!(rendered).unwrap();
+                Outcome::Rendered(None)
+            }
+        };
+        // Blank input lines, empty records and lines that can't be read show
+        // up as nothing but line breaks
+        if config.no_blank_lines
+            && rendered[content_start..]
+                .iter()
+                .all(u8::is_ascii_whitespace)
+        {
+            rendered.clear();
+        }
+        // A record is only empty if --no-blank-lines removed it
+        let (Outcome::Rendered(level), false) = (outcome, rendered.is_empty()) else {
+            rendered.clear();
+            if stop {
+                break;
+            }
+            continue;
+        };
+        if let Some(prefix) = &prefix {
+            prefixed.clear();
+            for line in rendered.split_inclusive(|byte| *byte == b'\n') {
+                prefixed.extend_from_slice(prefix.as_bytes());
+                prefixed.extend_from_slice(line);
+            }
+            std::mem::swap(&mut rendered, &mut prefixed);
+        }
+        match config.tail {
+            _ if config.count_lines || config.discover_schema => {}
+            Some(0) => {}
+            Some(keep) => {
+                // Reuse the buffer of the record that is being dropped
+                let mut record = Vec::new();
+                if tail.len() == keep {
+                    if let Some((_, dropped)) = tail.pop_front() {
+                        record = dropped;
+                        record.clear();
+                    }
+                }
+                record.extend_from_slice(&rendered);
+                tail.push_back((level, record));
             }
+            None => sink.write_record(level, &rendered).unwrap(),
+        }
+        written += 1;
+        rendered.clear();
+        if stop || head_reached(written) || limit_reached(state.counts.records) {
+            break;
         }
     }
+    for (level, record) in tail {
+        sink.write_record(level, &record).unwrap();
+    }
+    if config.count_lines {
+        let counts = format!("{}\n", state.counts);
+        sink.write_record(None, counts.as_bytes()).unwrap();
+    }
+    if config.discover_schema {
+        let schema = state.schema.to_string();
+        sink.write_record(None, schema.as_bytes()).unwrap();
+    }
+    sink.write_all_streams(styler.document_end()).unwrap();
+    sink.flush().unwrap();
+}
+
+/// Convert a line read from the input to a string, without its line ending
+///
+/// Invalid UTF-8 is an error, unless `lossy` is set in which case it is
+/// replaced with `�`.
+fn decode_line(mut line: Vec<u8>, lossy: bool) -> io::Result<String> {
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    match String::from_utf8(line) {
+        Ok(line) => Ok(line),
+        Err(e) if lossy => Ok(String::from_utf8_lossy(e.as_bytes()).into_owned()),
+        Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+    }
+}
+
+/// A right-aligned line number
+struct LineNumber(usize);
+
+impl fmt::Display for LineNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>4}", self.0)
+    }
 }
 
-fn process_line(
+fn process_line<'a>(
     json_line: String,
-    reusable: &mut Reusable<'_>,
-    out: &mut impl Write,
-    config: &cfg::Config,
+    reusable: &mut Reusable<'a>,
+    state: &mut StreamState,
+    out: &mut Vec<u8>,
+    config: &'a cfg::Config,
     styler: Styler,
-) {
+) -> Outcome {
+    debug_assert!(reusable.map.is_empty(), "the previous record wasn't reset");
     if !json_line.starts_with('{') {
-        writeln!(out, "{}", json_line).unwrap();
-        return;
+        if !keep_line(&json_line, None, config) {
+            return Outcome::Filtered;
+        }
+        state.counts.passthrough += 1;
+        write_passthrough(out, &json_line, config, styler);
+        return Outcome::Rendered(None);
+    }
+
+    if config.stream_render {
+        // --min-level and --filter conflict with --stream-render, so only
+        // filters on the raw line apply
+        if !keep_line(&json_line, None, config) {
+            return Outcome::Filtered;
+        }
+        return Outcome::Rendered(stream_line(&json_line, state, out, config, styler));
     }
 
     // SAFETY: the reusable map contents don't outlive the json_line
     //
     // This function does not return a result, so it's impossible to early exit
-    // accidentally with ?, and there are no `return` statements.
+    // accidentally with ?. The `return` statements above come before the map
+    // is filled, and there must be none below: the map is cleared at the end.
     let result = {
         let mut deserializer = unsafe {
             std::mem::transmute::<
@@ -113,18 +498,81 @@ fn process_line(
 
         let seed = deser::IndexMapSeed {
             map: &mut reusable.map,
+            trim_keys: config.trim_keys,
+            warn_duplicates_on_line: config.warn_duplicate_keys.then_some(state.line_number),
         };
         seed.deserialize(&mut deserializer)
     };
 
+    // Defaults are part of the record, so filters and every output format
+    // see them
+    if result.is_ok() {
+        for (key, value) in &config.field_defaults {
+            reusable
+                .map
+                .entry(key)
+                .or_insert(JsonValue::String(Cow::Borrowed(value)));
+        }
+    }
+
+    let mut outcome = Outcome::Filtered;
     match result {
+        Ok(()) if !keep_line(&json_line, Some(&reusable.map), config) => {}
+        Ok(()) if !watch_changed(&reusable.map, state, config) => {}
+        Ok(()) if config.discover_schema => state.schema.observe(&reusable.map),
         Ok(()) => {
-            if let Err(e) = json_to_logfmt(reusable, out, config, styler) {
+            if config.warn_type_mismatch {
+                warn_type_mismatch(&reusable.map, config);
+            }
+            let dimmed = config
+                .dim_when
+                .as_ref()
+                .is_some_and(|dim_when| dim_when.matches(&reusable.map));
+            let (styler, dim_end) = if dimmed {
+                write!(out, "{}", styler.dim_start()).unwrap();
+                (styler.plain(), styler.dim_end())
+            } else {
+                (styler, "")
+            };
+            let level = resolve_level(&reusable.map, config);
+            outcome = Outcome::Rendered(level);
+            state.counts.records += 1;
+            let new_error = config.highlight_new_errors
+                && level >= Some(Level::Error)
+                && resolve_message(&reusable.map, config)
+                    .is_some_and(|message| state.seen_errors.insert(message.to_string()));
+            for key in &config.exclude_fields {
+                reusable.map.shift_remove(key.as_str());
+            }
+            let start = out.len();
+            let deadline = config
+                .render_timeout
+                .map(|timeout| Instant::now() + timeout);
+            let result = match &config.template {
+                _ if config.output_format == cfg::OutputFormat::Json => {
+                    write_json(out, &reusable.map, config)
+                }
+                Some(template) => template.render(out, &reusable.map, styler),
+                None => json_to_logfmt(reusable, state, out, config, styler, new_error, deadline)
+                    .and_then(|()| match &config.empty_placeholder {
+                        Some(placeholder) if reusable.map.is_empty() => {
+                            write!(out, "{}", styler.text(placeholder))
+                        }
+                        _ => Ok(()),
+                    }),
+            };
+            if let Err(e) = result {
                 debug!("Failed to format JSON line: {}", e);
+                out.truncate(start);
+                write!(out, "{}", styler.text(&json_line)).unwrap();
+            }
+            writeln!(out, "{dim_end}").unwrap();
+            if config.keep_raw {
+                writeln!(out, "    {}", styler.text(&json_line)).unwrap();
+            }
+            if config.spacing {
                 writeln!(out).unwrap();
-                writeln!(out, "{}", json_line).unwrap();
             }
-            writeln!(out).unwrap();
         }
         Err(e) => {
             debug!(
@@ -132,143 +580,992 @@ fn process_line(
                 error = %e,
                 "Failed to deserialize JSON line",
             );
-            writeln!(out, "{}", json_line).unwrap();
+            if keep_line(&json_line, None, config) {
+                outcome = Outcome::Rendered(None);
+                state.counts.errors += 1;
+                write_parse_error(out, &json_line, &e, config, styler);
+            }
+        }
+    }
+    reusable.reset();
+    outcome
+}
+
+/// Write a line that isn't JSON as it is, after the --passthrough-prefix
+fn write_passthrough(out: &mut impl Write, line: &str, config: &cfg::Config, styler: Styler) {
+    if config.detect_and_skip_ansi_lines && line.contains('\x1b') {
+        if styler.markup == styler::Markup::Html {
+            // Raw escapes would show up as junk in a browser, and the line
+            // still needs escaping like any other text
+            writeln!(out, "{}", styler.text(&strip_ansi(line))).unwrap();
+        } else {
+            writeln!(out, "{line}").unwrap();
+        }
+        return;
+    }
+    if let Some(prefix) = &config.passthrough_prefix {
+        write!(out, "{}", styler.text(prefix)).unwrap();
+    }
+    writeln!(out, "{}", styler.text(line)).unwrap();
+}
+
+/// `line` without its ANSI escape sequences, like the `\x1b[32m` color codes
+fn strip_ansi(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            stripped.push(c);
+            continue;
+        }
+        // A CSI sequence runs until its final byte, anything else is a
+        // single escaped character
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if ('\x40'..='\x7e').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+    stripped
+}
+
+/// Write a line that failed to parse in the --error-format
+fn write_parse_error(
+    out: &mut impl Write,
+    line: &str,
+    error: &serde_json::Error,
+    config: &cfg::Config,
+    styler: Styler,
+) {
+    match config.error_format {
+        cfg::ErrorFormat::Text => write_passthrough(out, line, config, styler),
+        cfg::ErrorFormat::Json => {
+            let error = serde_json::json!({"jlp_error": error.to_string(), "line": line});
+            writeln!(out, "{error}").unwrap();
         }
     }
-    reusable.map.clear();
-    reusable.newline_fields.clear();
 }
 
-fn json_to_logfmt(
-    storage: &mut Reusable,
+/// The result of `process_line`
+enum Outcome {
+    /// The line was written, with the level of the record if it has one
+    Rendered(Option<Level>),
+    /// The line was hidden by a filter, nothing was written
+    Filtered,
+}
+
+/// Whether a line passes the filters: --grep, --min-level, --filter and
+/// --require
+///
+/// With --invert this is negated, so only lines that fail a filter are kept.
+/// Lines without a recognizable level are ignored by --min-level, so they are
+/// shown with or without --invert unless another filter hides them. Lines
+/// that aren't JSON records fail --filter but pass --require.
+fn keep_line(
+    raw: &str,
+    record: Option<&FnvIndexMap<&str, JsonValue>>,
+    config: &cfg::Config,
+) -> bool {
+    if config.grep.is_none()
+        && config.min_level.is_none()
+        && config.filter.is_none()
+        && config.require.is_empty()
+    {
+        return true;
+    }
+    let level = record.and_then(|record| resolve_level(record, config));
+    let other_filters =
+        config.grep.is_some() || config.filter.is_some() || !config.require.is_empty();
+    let matches = config.grep.as_ref().is_none_or(|re| re.is_match(raw))
+        && config
+            .filter
+            .as_ref()
+            .is_none_or(|filter| record.is_some_and(|record| filter.matches(record)))
+        && record.is_none_or(|record| {
+            config
+                .require
+                .iter()
+                .all(|field| field.get(record).is_some())
+        });
+    match config
+        .min_level
+        .map(|min_level| level.map(|level| level >= min_level))
+    {
+        Some(None) if !other_filters => true,
+        Some(None) | None => matches != config.invert,
+        Some(Some(level_matches)) => (matches && level_matches) != config.invert,
+    }
+}
+
+/// Whether the --watch field of a record changed since the last record that
+/// was shown, remembering its new value if it did
+fn watch_changed(
+    record: &FnvIndexMap<&str, JsonValue>,
+    state: &mut StreamState,
+    config: &cfg::Config,
+) -> bool {
+    let Some(field) = &config.watch else {
+        return true;
+    };
+    let Some(value) = field.get(record) else {
+        return false;
+    };
+    let value = serde_json::to_value(value).expect("JSON values are valid JSON");
+    if state.watched.as_ref() == Some(&value) {
+        return false;
+    }
+    state.watched = Some(value);
+    true
+}
+
+/// Render a record one field at a time as it is parsed, for --stream-render
+///
+/// Fields are written in the order they appear, so --no-key-fields are shown
+/// without their key wherever they are in the record, and multi-line values
+/// are not moved to the end.
+fn stream_line(
+    json_line: &str,
+    state: &mut StreamState,
     out: &mut impl Write,
     config: &cfg::Config,
     styler: Styler,
+) -> Option<Level> {
+    let mut level = None;
+    let mut first = true;
+    let deadline = config
+        .render_timeout
+        .map(|timeout| Instant::now() + timeout);
+    let seed = deser::EntrySeed {
+        on_entry: |key: &str, value: JsonValue| {
+            let unkeyed =
+                is_unkeyable(&value) && config.no_key_fields.iter().any(|field| field == key);
+            if deadline_passed(deadline) {
+                return Err(render_timed_out());
+            }
+            if !first {
+                let separator = if unkeyed { " " } else { &config.separator };
+                write!(out, "{separator}")?;
+            }
+            first = false;
+            if key == config.level_field {
+                level = parse_level_value(&value, config);
+            }
+            if unkeyed {
+                write_unkeyed(out, key, &value, false, state, config, styler)
+            } else {
+                display_field(out, &value, key, config, styler, deadline)
+            }
+        },
+        trim_keys: config.trim_keys,
+    };
+    let mut deserializer = serde_json::Deserializer::from_str(json_line);
+    match seed.deserialize(&mut deserializer) {
+        Ok(()) => {
+            state.counts.records += 1;
+            writeln!(out).unwrap();
+            if config.keep_raw {
+                writeln!(out, "    {}", styler.text(json_line)).unwrap();
+            }
+            if config.spacing {
+                writeln!(out).unwrap();
+            }
+        }
+        Err(e) => {
+            debug!(
+                line = %json_line,
+                error = %e,
+                "Failed to stream JSON line",
+            );
+            state.counts.errors += 1;
+            if !first {
+                writeln!(out).unwrap();
+            }
+            write_parse_error(out, json_line, &e, config, styler);
+        }
+    }
+    level
+}
+
+/// The level of a record, if it has a recognizable one
+fn resolve_level(map: &FnvIndexMap<&str, JsonValue>, config: &cfg::Config) -> Option<Level> {
+    level_value(map, config).and_then(|value| parse_level_value(value, config))
+}
+
+/// The level of a level field value, a name or a --level-map number
+fn parse_level_value(value: &JsonValue, config: &cfg::Config) -> Option<Level> {
+    match value {
+        JsonValue::String(level) => Level::parse(level),
+        JsonValue::Number(level) => config.numeric_level(level),
+        _ => None,
+    }
+}
+
+/// The value of the --level-field
+///
+/// A top-level key that matches the --level-field exactly takes precedence
+/// over a nested value at that path.
+fn level_value<'m, 'a>(
+    map: &'m FnvIndexMap<&'a str, JsonValue<'a>>,
+    config: &cfg::Config,
+) -> Option<&'m JsonValue<'a>> {
+    match (map.get(config.level_field.as_str()), &config.level_path) {
+        (None, Some(path)) => path.get(map),
+        (value, _) => value,
+    }
+}
+
+/// Warn about level and timestamp fields with values that can't be shown as
+/// levels or timestamps, for --warn-type-mismatch
+fn warn_type_mismatch(map: &FnvIndexMap<&str, JsonValue>, config: &cfg::Config) {
+    match level_value(map, config) {
+        None | Some(JsonValue::String(_)) => {}
+        Some(JsonValue::Number(level)) if config.numeric_level(level).is_some() => {}
+        Some(value) => warn!(
+            field = %config.level_field,
+            found = %value.type_name(),
+            "level field is not a string",
+        ),
+    }
+    match map.get(config.timestamp_field.as_str()) {
+        None | Some(JsonValue::Number(_) | JsonValue::String(_)) => {}
+        Some(value) => warn!(
+            field = %config.timestamp_field,
+            found = %value.type_name(),
+            "timestamp field is not a number or string",
+        ),
+    }
+}
+
+/// The --no-key-fields field that holds the message, if any
+///
+/// This is the first string field that isn't the level or timestamp.
+fn message_key<'c>(map: &FnvIndexMap<&str, JsonValue>, config: &'c cfg::Config) -> Option<&'c str> {
+    let is_message = |key: &&String| matches!(map.get(key.as_str()), Some(JsonValue::String(_)));
+    if !config.message_fields.is_empty() {
+        return config
+            .message_fields
+            .iter()
+            .find(is_message)
+            .map(String::as_str);
+    }
+    config
+        .no_key_fields
+        .iter()
+        .filter(|key| **key != config.level_field && **key != config.timestamp_field)
+        .find(is_message)
+        .map(String::as_str)
+}
+
+/// The message of a record, from --message-pointer or the `message_key`
+fn resolve_message<'m>(
+    map: &'m FnvIndexMap<&str, JsonValue>,
+    config: &cfg::Config,
+) -> Option<&'m str> {
+    let value = match &config.message_pointer {
+        Some(pointer) => pointer.get(map),
+        None => message_key(map, config).and_then(|key| map.get(key)),
+    };
+    match value {
+        Some(JsonValue::String(message)) => Some(message),
+        _ => None,
+    }
+}
+
+/// Write a record in logfmt-ish form
+///
+/// If `emphasize_message` is set the message is rendered with emphasis.
+fn json_to_logfmt<'a>(
+    storage: &mut Reusable<'a>,
+    state: &mut StreamState,
+    out: &mut impl Write,
+    config: &'a cfg::Config,
+    styler: Styler,
+    emphasize_message: bool,
+    deadline: Option<Instant>,
 ) -> io::Result<()> {
     storage.newline_fields.clear();
+    storage.trailing.clear();
+    storage.array_diffs.clear();
+    if let Some(only_fields) = &config.only_fields {
+        for (key, value) in storage.map.iter_mut() {
+            if !only_fields.iter().any(|field| field == key) {
+                *value = JsonValue::Removed;
+            }
+        }
+    }
+    for field in &config.show_changes_for {
+        let Some(value) = storage.map.get_mut(field.as_str()) else {
+            continue;
+        };
+        let current = serde_json::to_value(&*value).expect("JSON values are valid JSON");
+        match state.previous_values.get_mut(field) {
+            Some(previous) if *previous == current => *value = JsonValue::Removed,
+            Some(previous) => {
+                if let (serde_json::Value::Array(previous), serde_json::Value::Array(current)) =
+                    (&previous, &current)
+                {
+                    storage
+                        .array_diffs
+                        .push(ArrayDiff::new(field, previous, current));
+                }
+                *previous = current;
+            }
+            None => {
+                state.previous_values.insert(field.clone(), current);
+            }
+        }
+    }
+    let emphasized_key = match config.message_pointer {
+        None if emphasize_message => message_key(&storage.map, config),
+        _ => None,
+    };
     let mut first = true;
+    // Keys at or after this index are printed with their key
+    let header_end = if config.no_key_fields_only_if_first {
+        storage
+            .map
+            .keys()
+            .position(|key| !config.no_key_fields.iter().any(|field| field == key))
+            .unwrap_or(storage.map.len())
+    } else {
+        storage.map.len()
+    };
+    storage.header_order.clear();
+    storage.header_order.extend(0..config.no_key_fields.len());
+    if config.header_order == cfg::HeaderOrder::Json {
+        // Fields that aren't top-level keys, like a nested level, go last
+        let map = &storage.map;
+        storage.header_order.sort_by_key(|&field| {
+            map.get_index_of(config.no_key_fields[field].as_str())
+                .unwrap_or(usize::MAX)
+        });
+    }
     // Print fields specified in no_key_fields first if they exist
-    for key in &config.no_key_fields {
-        if let Some(value) = storage.map.get_mut(key.as_str()) {
-            if !first {
-                write!(out, " ")?;
-            } else {
-                first = false;
+    for &field in &storage.header_order {
+        let key = &config.no_key_fields[field];
+        if config.message_fields.contains(key) {
+            // Shown after the header, if it is the message
+            continue;
+        }
+        let value = match storage.map.get_index_of(key.as_str()) {
+            Some(index) if index >= header_end => continue,
+            Some(index) => &mut storage.map[index],
+            // A nested level is promoted out of its parent
+            None if *key == config.level_field => {
+                match config
+                    .level_path
+                    .as_ref()
+                    .and_then(|path| path.get_mut(&mut storage.map))
+                {
+                    Some(value) => value,
+                    None => continue,
+                }
             }
-            match value {
-                JsonValue::String(val_str) => {
-                    if key == &config.level_field {
-                        write!(out, "{}", styler.level(val_str))?;
-                    } else {
-                        write!(out, "{}", val_str)?;
-                    }
+            None => continue,
+        };
+        if !is_unkeyable(value) {
+            continue;
+        }
+        if config.hide_info_level
+            && *key == config.level_field
+            && parse_level_value(value, config) == Some(Level::Info)
+        {
+            *value = JsonValue::Removed;
+            continue;
+        }
+        if *key == config.timestamp_field && config.timestamp_position == cfg::Position::End {
+            write_unkeyed(
+                &mut storage.trailing,
+                key,
+                value,
+                false,
+                state,
+                config,
+                styler,
+            )?;
+            *value = JsonValue::Removed;
+            continue;
+        }
+        if !first {
+            write!(out, " ")?;
+        } else {
+            first = false;
+        }
+        let emphasize = emphasized_key == Some(key.as_str());
+        write_unkeyed(out, key, value, emphasize, state, config, styler)?;
+        *value = JsonValue::Removed;
+    }
+
+    if let Some(pointer) = &config.message_pointer {
+        if let Some(value) = pointer.get_mut(&mut storage.map) {
+            if let JsonValue::String(message) = value {
+                if !first {
+                    write!(out, " ")?;
                 }
-                JsonValue::Number(num) => {
-                    if key == &config.timestamp_field {
-                        let timestamp = num.as_i64().unwrap_or_default();
-                        if config.timestamp_format != cfg::TimestampFormat::Raw {
-                            try_format_datetime(
-                                &config.timestamp_format,
-                                timestamp,
-                                out,
-                                styler,
-                                &config.millis_out_format,
-                                &config.secs_out_format,
-                            )?;
-                        } else {
-                            write!(out, "{}", timestamp)?;
-                        }
-                    } else {
-                        write!(out, "{}", num)?;
-                    }
+                if emphasize_message {
+                    write!(out, "{}", styler.emphasis(message))?;
+                } else {
+                    write!(out, "{}", styler.text(message))?;
                 }
-                _ => continue,
+                first = false;
+                *value = JsonValue::Removed;
+            }
+        }
+    }
+
+    if config.message_pointer.is_none() && !config.message_fields.is_empty() {
+        if let Some(key) = message_key(&storage.map, config) {
+            let value = storage
+                .map
+                .get_mut(key)
+                .expect("the message key is in the record");
+            if !first {
+                write!(out, " ")?;
             }
+            write_unkeyed(out, key, value, emphasize_message, state, config, styler)?;
+            first = false;
             *value = JsonValue::Removed;
         }
     }
 
+    if config.sort_keys {
+        storage.map.sort_keys();
+    }
+    if config.sort_by_value {
+        storage
+            .map
+            .sort_by(|a_key, a, b_key, b| by_value(a_key, a, b_key, b));
+    }
+
     // Print the rest of the fields, excluding Removed variants
+    let mut shown = 0;
+    let mut hidden = 0;
     for (index, (key, value)) in storage.map.iter().enumerate() {
+        if is_hidden(value, config) {
+            continue;
+        }
+        if deadline_passed(deadline) {
+            return Err(render_timed_out());
+        }
+        if config.max_fields_shown.is_some_and(|max| shown >= max) {
+            hidden += 1;
+            continue;
+        }
+        shown += 1;
         match value {
-            JsonValue::Removed => continue,
-            JsonValue::String(val_str) if val_str.contains('\n') => {
+            JsonValue::String(val_str)
+                if val_str.contains('\n') || config.trace_fields.iter().any(|f| f == key) =>
+            {
                 storage.newline_fields.push(index);
                 continue;
             }
+            JsonValue::Object(inner) if config.inline_fields.iter().any(|f| f == key) => {
+                for (key, value) in inner {
+                    if is_hidden(value, config) {
+                        continue;
+                    }
+                    if !first {
+                        write!(out, "{}", config.separator)?;
+                    }
+                    display_value_recursive(out, value, key, 0, config, styler, deadline)?;
+                    first = false;
+                }
+            }
             _ => {
                 if !first {
-                    write!(out, " ").unwrap();
+                    write!(out, "{}", config.separator)?;
+                }
+                match storage.array_diffs.iter().find(|diff| diff.field == *key) {
+                    Some(diff) => write_array_diff(out, key, diff, styler)?,
+                    None => display_field(out, value, key, config, styler, deadline)?,
                 }
-                display_value_recursive(out, value, key, 0, styler)?;
                 first = false;
             }
         }
     }
+    if hidden > 0 {
+        if !first {
+            write!(out, "{}", config.separator)?;
+        }
+        write!(out, "…(+{hidden} more)")?;
+        first = false;
+    }
+
+    if !storage.trailing.is_empty() {
+        if !first {
+            write!(out, "{}", config.separator)?;
+        }
+        out.write_all(&storage.trailing)?;
+        storage.trailing.clear();
+    }
 
-    // Print fields containing newlines at the end
+    // Print fields containing newlines and stack traces at the end
     for index in &storage.newline_fields {
         writeln!(out).unwrap();
         let (key, value) = storage
             .map
             .get_index(*index)
             .expect("valid indices created");
-        display_value_recursive(out, value, key, 0, styler)?;
-    }
-
+        match value {
+            JsonValue::String(trace) if config.trace_fields.iter().any(|f| f == key) => {
+                write_trace(out, key, trace, styler)?;
+            }
+            _ => display_value_recursive(out, value, key, 0, config, styler, deadline)?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Write the record as a line of JSON, for --output-format=json
+fn write_json(
+    out: &mut impl Write,
+    record: &FnvIndexMap<&str, JsonValue>,
+    config: &cfg::Config,
+) -> io::Result<()> {
+    if config.unflatten_dotted_output {
+        serde_json::to_writer(&mut *out, &unflatten(record)?)?;
+    } else {
+        serde_json::to_writer(&mut *out, record)?;
+    }
+    Ok(())
+}
+
+/// The record with dotted keys nested, for --unflatten-dotted-output
+fn unflatten(
+    record: &FnvIndexMap<&str, JsonValue>,
+) -> io::Result<serde_json::Map<String, serde_json::Value>> {
+    let mut nested = serde_json::Map::new();
+    for (key, value) in record {
+        let value = serde_json::to_value(value)?;
+        if let Err(value) = insert_nested(&mut nested, key, value) {
+            nested.insert(key.to_string(), value);
+        }
+    }
+    Ok(nested)
+}
+
+/// Insert `value` at the dotted `path` in `object`, creating objects as needed
+///
+/// Returns the value if part of the path already has a value that isn't an
+/// object.
+fn insert_nested(
+    object: &mut serde_json::Map<String, serde_json::Value>,
+    path: &str,
+    value: serde_json::Value,
+) -> Result<(), serde_json::Value> {
+    let Some((first, rest)) = path.split_once('.') else {
+        object.insert(path.to_string(), value);
+        return Ok(());
+    };
+    let parent = object
+        .entry(first)
+        .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+    match parent {
+        serde_json::Value::Object(parent) => insert_nested(parent, rest, value),
+        _ => Err(value),
+    }
+}
+
+/// Write a changed array with added elements marked `+` and removed elements
+/// marked `-` at the end
+fn write_array_diff(
+    out: &mut impl Write,
+    key: &str,
+    diff: &ArrayDiff,
+    styler: Styler,
+) -> io::Result<()> {
+    write!(out, "{}", styler.depth_multi(key, "[", 0))?;
+    for (index, (element, change)) in diff.elements.iter().enumerate() {
+        if index > 0 {
+            write!(out, " ")?;
+        }
+        let text = match element {
+            serde_json::Value::String(text) => Cow::Borrowed(text.as_str()),
+            element => Cow::Owned(element.to_string()),
+        };
+        match change {
+            Change::Same => write!(out, "{}", styler.text(&text))?,
+            Change::Added => write!(out, "{}", styler.added(&format!("+{text}")))?,
+            Change::Removed => write!(out, "{}", styler.removed(&format!("-{text}")))?,
+        }
+    }
+    write!(out, "{}", styler.depth("]", 0))
+}
+
+/// Whether a keyed field is skipped entirely
+fn is_hidden(value: &JsonValue, config: &cfg::Config) -> bool {
+    match value {
+        JsonValue::Removed => true,
+        JsonValue::Bool(false) => config.bool_as_flag,
+        _ => false,
+    }
+}
+
+/// Write a --trace-field with each frame on its own line, indented below the
+/// key
+fn write_trace(out: &mut impl Write, key: &str, trace: &str, styler: Styler) -> io::Result<()> {
+    write!(out, "{}:", styler.depth(key, 0))?;
+    // Pieces after a `\tat ` lose their `at` when they are split
+    let frames = trace.lines().flat_map(|line| {
+        let mut pieces = line.split("\tat ");
+        let first = pieces.next().map(|piece| ("", piece));
+        first.into_iter().chain(pieces.map(|piece| ("at ", piece)))
+    });
+    for (at, frame) in frames {
+        let frame = frame.trim();
+        if frame.is_empty() {
+            continue;
+        }
+        let indent = if !at.is_empty() || frame.starts_with("at ") {
+            "        "
+        } else {
+            "    "
+        };
+        write!(out, "\n{indent}{at}{}", styler.text(frame))?;
+    }
     Ok(())
 }
 
+/// Order numeric fields by value, largest first, followed by all other fields
+/// by key, for --sort-by-value
+fn by_value(a_key: &str, a: &JsonValue, b_key: &str, b: &JsonValue) -> std::cmp::Ordering {
+    let number = |value: &JsonValue| match value {
+        JsonValue::Number(n) => n.as_f64(),
+        _ => None,
+    };
+    match (number(a), number(b)) {
+        (Some(a), Some(b)) => b.total_cmp(&a),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => a_key.cmp(b_key),
+    }
+}
+
+/// `s` with quotes and backslashes escaped, if it contains anything that
+/// means it must be quoted
+fn escape_for_quotes(s: &str, config: &cfg::Config) -> Option<String> {
+    let quote = config.quote_style.quote();
+    if s.contains(' ') || s.contains(quote) || s.contains('\\') {
+        Some(s.replace('\\', r"\\").replace(quote, &format!("\\{quote}")))
+    } else {
+        None
+    }
+}
+
+/// Whether `value` can be shown without its key
+fn is_unkeyable(value: &JsonValue) -> bool {
+    matches!(value, JsonValue::String(_) | JsonValue::Number(_))
+}
+
+/// Write a --no-key-fields value without its key
+///
+/// Values that aren't `is_unkeyable` are skipped.
+fn write_unkeyed(
+    out: &mut impl Write,
+    key: &str,
+    value: &JsonValue,
+    emphasize: bool,
+    state: &mut StreamState,
+    config: &cfg::Config,
+    styler: Styler,
+) -> io::Result<()> {
+    match value {
+        JsonValue::String(original) => {
+            // Epoch seconds or millis, possibly with a fraction, as a string
+            if key == config.timestamp_field {
+                if let Ok(num) = original.parse::<serde_json::Number>() {
+                    return write_timestamp(&num, out, config, styler, state);
+                }
+                let formatted = !matches!(
+                    config.timestamp_format,
+                    cfg::TimestampFormat::Raw | cfg::TimestampFormat::Uptime
+                );
+                if let (true, Ok(dt)) = (formatted, DateTime::parse_from_rfc3339(original)) {
+                    let dt = dt.to_utc();
+                    let subsecond = dt.timestamp_subsec_nanos() != 0;
+                    return write_datetime(dt, subsecond, out, config, styler, state);
+                }
+            }
+            let decoded = decode_base64_field(key, original, config);
+            let original = decoded.as_deref().unwrap_or(original);
+            let escaped = if config.quote_header {
+                escape_for_quotes(original, config)
+            } else {
+                None
+            };
+            let val_str = escaped.as_deref().unwrap_or(original);
+            let styled = if key == config.level_field {
+                styler.level(val_str)
+            } else if config.component_field.as_deref() == Some(key) {
+                styler.component(val_str)
+            } else if config.hash_color_fields.iter().any(|field| field == key) {
+                styler.hashed(val_str, original)
+            } else if emphasize {
+                styler.emphasis(val_str)
+            } else {
+                styler.text(val_str)
+            };
+            if escaped.is_some() {
+                let quote = config.quote_style.quote();
+                write!(out, "{quote}{styled}{quote}")
+            } else {
+                write!(out, "{styled}")
+            }
+        }
+        JsonValue::Number(num) => {
+            let level = match key == config.level_field {
+                true => config.numeric_level(num),
+                false => None,
+            };
+            if key == config.timestamp_field {
+                write_timestamp(num, out, config, styler, state)
+            } else if let Some(level) = level {
+                write!(out, "{}", styler.level(level.name()))
+            } else {
+                write!(out, "{}", NumberDisplay(num, config))
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The decoded text of a --decode-base64 field, if it is base64 encoded UTF-8
+fn decode_base64_field(key: &str, value: &str, config: &cfg::Config) -> Option<String> {
+    if !config.decode_base64.iter().any(|field| field == key) {
+        return None;
+    }
+    String::from_utf8(base64::decode(value)?).ok()
+}
+
+/// Write an epoch timestamp in the --timestamp-format
+///
+/// Timestamps with a fraction of a second are shown with millis.
+fn write_timestamp(
+    num: &serde_json::Number,
+    out: &mut impl Write,
+    config: &cfg::Config,
+    styler: Styler,
+    state: &mut StreamState,
+) -> io::Result<()> {
+    let format = config.timestamp_format;
+    match (format, num.as_i64()) {
+        (cfg::TimestampFormat::Uptime, _) => {
+            let uptime = Uptime(num.as_f64().unwrap_or_default());
+            write!(out, "{}", styler.timestamp(&uptime))
+        }
+        (cfg::TimestampFormat::Raw, _) => write!(out, "{num}"),
+        (_, Some(timestamp)) => {
+            try_format_datetime(timestamp, num, format, out, config, styler, state)
+        }
+        (_, None) => {
+            let timestamp = num.as_f64().unwrap_or_default();
+            let unit = match format {
+                cfg::TimestampFormat::Auto if timestamp.abs() > NANOS_THRESHOLD as f64 => {
+                    cfg::TimestampFormat::Nanos
+                }
+                cfg::TimestampFormat::Auto if timestamp.abs() > YEAR_3K_EPOCH as f64 => {
+                    cfg::TimestampFormat::Millis
+                }
+                cfg::TimestampFormat::Auto => cfg::TimestampFormat::Seconds,
+                unit => unit,
+            };
+            // Keep the fraction of seconds as millis
+            let (timestamp, unit) = match unit {
+                cfg::TimestampFormat::Seconds => (timestamp * 1000.0, cfg::TimestampFormat::Millis),
+                unit => (timestamp, unit),
+            };
+            let timestamp = timestamp.round() as i64;
+            try_format_datetime(timestamp, num, unit, out, config, styler, state)
+        }
+    }
+}
+
+/// Write `timestamp` as a date, or as it is if it is out of range
+///
+/// `raw` is the original value, for --dual-timestamp.
 fn try_format_datetime(
-    timestamp_format: &cfg::TimestampFormat,
     timestamp: i64,
+    raw: &serde_json::Number,
+    format: cfg::TimestampFormat,
     out: &mut impl Write,
+    config: &cfg::Config,
     styler: Styler,
-    millis_out_format: &[Item],
-    secs_out_format: &[Item],
+    state: &mut StreamState,
 ) -> Result<(), io::Error> {
-    let mut tsfmt = *timestamp_format;
-    let iso_datetime = match timestamp_format {
-        cfg::TimestampFormat::Auto if timestamp > YEAR_3K_EPOCH => {
+    let mut tsfmt = format;
+    let iso_datetime = match format {
+        cfg::TimestampFormat::Auto if timestamp.unsigned_abs() > NANOS_THRESHOLD => {
+            tsfmt = cfg::TimestampFormat::Nanos;
+            datetime_from_nanos(timestamp)
+        }
+        cfg::TimestampFormat::Auto if timestamp.unsigned_abs() > YEAR_3K_EPOCH => {
             tsfmt = cfg::TimestampFormat::Millis;
-            DateTime::<Utc>::from_timestamp(timestamp / 1000, (timestamp % 1000 * 1_000_000) as u32)
+            datetime_from_millis(timestamp)
         }
         cfg::TimestampFormat::Auto => {
             tsfmt = cfg::TimestampFormat::Seconds;
             DateTime::<Utc>::from_timestamp(timestamp, 0)
         }
         cfg::TimestampFormat::Seconds => DateTime::<Utc>::from_timestamp(timestamp, 0),
-        cfg::TimestampFormat::Millis => {
-            DateTime::<Utc>::from_timestamp(timestamp / 1000, (timestamp % 1000 * 1_000_000) as u32)
+        cfg::TimestampFormat::Millis => datetime_from_millis(timestamp),
+        cfg::TimestampFormat::Nanos => datetime_from_nanos(timestamp),
+        cfg::TimestampFormat::Uptime | cfg::TimestampFormat::Raw => {
+            unreachable!(
+                "Uptime and Raw timestamp formats should not be used in maybe_format_datetime"
+            )
         }
-        cfg::TimestampFormat::Raw => {
-            unreachable!("Raw timestamp format should not be used in maybe_format_datetime")
+    };
+
+    match iso_datetime {
+        Some(dt) => {
+            let subsecond = tsfmt != cfg::TimestampFormat::Seconds;
+            write_datetime(dt, subsecond, out, config, styler, state)?;
+            if config.dual_timestamp {
+                write!(out, "{}", styler.timestamp(&format!("({raw})")))?;
+            }
+            Ok(())
         }
+        None => write!(out, "{}", styler.timestamp(&timestamp)),
+    }
+}
+
+/// Write a timestamp in the configured timezone and format
+///
+/// `subsecond` picks the format for millisecond timestamps over the one for
+/// second timestamps.
+fn write_datetime(
+    dt: DateTime<Utc>,
+    subsecond: bool,
+    out: &mut impl Write,
+    config: &cfg::Config,
+    styler: Styler,
+    state: &mut StreamState,
+) -> io::Result<()> {
+    let dt = config.timezone.convert(dt);
+    let time_only = config.compact_timestamp && {
+        let date = dt.date_naive();
+        state.previous_date.replace(date) == Some(date)
     };
+    let format = match (time_only, subsecond) {
+        (true, true) => &config.time_only_millis_out_format,
+        (true, false) => &config.time_only_secs_out_format,
+        (false, true) => &config.millis_out_format,
+        (false, false) => &config.secs_out_format,
+    };
+    write!(
+        out,
+        "{}",
+        styler.timestamp(&dt.format_with_items(format.iter()))
+    )
+}
 
-    match (iso_datetime, tsfmt) {
-        (Some(dt), cfg::TimestampFormat::Seconds) => {
-            write!(
-                out,
-                "{}",
-                styler.timestamp(&dt.format_with_items(secs_out_format.iter()))
-            )
-            .unwrap();
+/// Seconds since boot, displayed as `+HH:MM:SS.mmm`
+struct Uptime(f64);
+
+impl fmt::Display for Uptime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0.0 { '-' } else { '+' };
+        let millis = (self.0.abs() * 1000.0).round() as u64;
+        let (secs, millis) = (millis / 1000, millis % 1000);
+        let (hours, mins, secs) = (secs / 3600, secs / 60 % 60, secs % 60);
+        write!(f, "{sign}{hours:02}:{mins:02}:{secs:02}.{millis:03}")
+    }
+}
+
+/// Convert epoch millis to a datetime
+///
+/// Uses euclidean division so that negative (pre-1970) timestamps end up with
+/// a positive nanosecond component.
+fn datetime_from_millis(millis: i64) -> Option<DateTime<Utc>> {
+    DateTime::<Utc>::from_timestamp(
+        millis.div_euclid(1000),
+        (millis.rem_euclid(1000) * 1_000_000) as u32,
+    )
+}
+
+fn datetime_from_nanos(nanos: i64) -> Option<DateTime<Utc>> {
+    DateTime::<Utc>::from_timestamp(
+        nanos.div_euclid(1_000_000_000),
+        nanos.rem_euclid(1_000_000_000) as u32,
+    )
+}
+
+/// Displays a number according to the `config`
+struct NumberDisplay<'a>(&'a serde_json::Number, &'a cfg::Config);
+
+impl fmt::Display for NumberDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let NumberDisplay(num, config) = *self;
+        match num.as_f64() {
+            Some(float) if config.expand_scientific && num.is_f64() => {
+                let magnitude = float.abs();
+                if float == 0.0 || (1e-16..1e21).contains(&magnitude) {
+                    // Rust never uses scientific notation to display floats
+                    write!(f, "{float}")
+                } else {
+                    write!(f, "{num}")
+                }
+            }
+            _ => write!(f, "{num}"),
         }
-        (Some(dt), cfg::TimestampFormat::Millis) => {
-            write!(
-                out,
-                "{}",
-                styler.timestamp(&dt.format_with_items(millis_out_format.iter()))
-            )
-            .unwrap();
+    }
+}
+
+/// The number of nanoseconds in the unit that `key` is suffixed with
+fn duration_unit_nanos(key: &str) -> Option<f64> {
+    let (_, suffix) = key.rsplit_once('_')?;
+    match suffix {
+        "ns" => Some(1.0),
+        "us" => Some(1e3),
+        "ms" => Some(1e6),
+        "sec" => Some(1e9),
+        _ => None,
+    }
+}
+
+/// A duration in nanoseconds, displayed like `250ms` or `1h2m3.5s`
+struct HumanDuration(f64);
+
+impl fmt::Display for HumanDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        /// Up to 3 decimal places, without trailing zeros
+        fn short(value: f64) -> String {
+            let value = format!("{value:.3}");
+            value
+                .trim_end_matches('0')
+                .trim_end_matches('.')
+                .to_string()
+        }
+
+        if self.0 < 0.0 {
+            f.write_str("-")?;
         }
-        _ => {
-            write!(out, "{}", styler.timestamp(&timestamp))?;
+        let nanos = self.0.abs();
+        if nanos < 1e3 {
+            write!(f, "{}ns", short(nanos))
+        } else if nanos < 1e6 {
+            write!(f, "{}µs", short(nanos / 1e3))
+        } else if nanos < 1e9 {
+            write!(f, "{}ms", short(nanos / 1e6))
+        } else if nanos < 60e9 {
+            write!(f, "{}s", short(nanos / 1e9))
+        } else {
+            let secs = nanos / 1e9;
+            let (hours, mins) = ((secs / 3600.0) as u64, (secs / 60.0 % 60.0) as u64);
+            if hours > 0 {
+                write!(f, "{hours}h")?;
+            }
+            write!(f, "{mins}m")?;
+            let secs = short(secs % 60.0);
+            if secs != "0" {
+                write!(f, "{secs}s")?;
+            }
+            Ok(())
         }
     }
-
-    Ok(())
 }
 
 fn display_value_recursive(
@@ -276,55 +1573,240 @@ fn display_value_recursive(
     value: &JsonValue,
     prefix: &str,
     depth: usize,
+    config: &cfg::Config,
     styler: Styler,
+    deadline: Option<Instant>,
 ) -> io::Result<()> {
     trace!(?value, ?depth, "display_value_recursive");
+    let translated;
+    let value = match config
+        .lookups
+        .iter()
+        .find(|l| depth == 0 && l.field == prefix)
+    {
+        Some(lookup) => {
+            let text = match value {
+                JsonValue::String(s) => lookup.get(s),
+                JsonValue::Number(n) => lookup.get(&n.to_string()),
+                _ => None,
+            };
+            match text {
+                Some(text) => {
+                    translated = JsonValue::String(Cow::Borrowed(text));
+                    &translated
+                }
+                None => value,
+            }
+        }
+        None => value,
+    };
+    let label = match config
+        .labels
+        .iter()
+        .find(|(key, _)| depth == 0 && key == prefix)
+    {
+        Some((_, label)) => label.as_str(),
+        None => prefix,
+    };
     let (colored_prefix, sep) = if prefix.is_empty() {
         (styler.empty(), "")
     } else {
-        (styler.depth(prefix, depth), "=")
+        (styler.depth(label, depth), "=")
     };
 
     match value {
         JsonValue::String(s) => {
-            if s.contains(' ') || s.contains('"') || s.contains('\\') {
-                let val = s.replace('\\', r"\\").replace('"', r#"\""#);
-                write!(out, r#"{colored_prefix}{sep}"{val}""#)
+            let decoded = match depth {
+                0 => decode_base64_field(prefix, s, config),
+                _ => None,
+            };
+            let s = decoded.as_deref().unwrap_or(s);
+            let shortened = match config.shorten_paths {
+                true => shorten_path(s),
+                false => None,
+            };
+            let s = shortened.as_deref().unwrap_or(s);
+            let is_component = depth == 0 && config.component_field.as_deref() == Some(prefix);
+            let is_hashed = depth == 0 && config.hash_color_fields.iter().any(|f| f == prefix);
+            let style = |val| {
+                if is_component {
+                    styler.component(val)
+                } else if is_hashed {
+                    styler.hashed(val, s)
+                } else if config.color_by_type {
+                    styler.named(val, NamedStyle::Color(AnsiColors::Green))
+                } else {
+                    styler.text(val)
+                }
+            };
+            let (shown, cut) = truncate_value(s, config);
+            if let Some(val) = escape_for_quotes(shown, config) {
+                let quote = config.quote_style.quote();
+                let val = style(&val);
+                write!(out, "{colored_prefix}{sep}{quote}{val}{quote}")?;
             } else {
-                write!(out, "{colored_prefix}{sep}{s}")
+                let shown = style(shown);
+                write!(out, "{colored_prefix}{sep}{shown}")?;
+            }
+            if cut > 0 {
+                write!(out, "…({cut} more)")?;
+            }
+            Ok(())
+        }
+        JsonValue::Number(n) if depth == 0 && config.hex_fields.iter().any(|f| f == prefix) => {
+            match (n.as_u64(), n.as_i64()) {
+                (Some(n), _) => write!(out, "{colored_prefix}{sep}{n:#x}"),
+                (None, Some(n)) => write!(out, "{colored_prefix}{sep}-{:#x}", n.unsigned_abs()),
+                // Not an integer
+                (None, None) => write!(out, "{colored_prefix}{sep}{}", NumberDisplay(n, config)),
+            }
+        }
+        JsonValue::Number(n) => {
+            let duration = match duration_unit_nanos(prefix) {
+                Some(nanos) if config.auto_humanize => n.as_f64().map(|n| n * nanos),
+                _ => None,
+            };
+            let (duration, number) = (duration.map(HumanDuration), NumberDisplay(n, config));
+            let text: &dyn fmt::Display = match &duration {
+                Some(duration) => duration,
+                None => &number,
+            };
+            let gradient = config
+                .gradients
+                .iter()
+                .find(|gradient| depth == 0 && gradient.field == prefix)
+                .zip(n.as_f64());
+            match gradient {
+                Some((gradient, value)) => {
+                    let text = text.to_string();
+                    let text = styler.gradient(&text, gradient.position(value));
+                    write!(out, "{colored_prefix}{sep}{text}")
+                }
+                None if config.color_by_type => {
+                    let text = text.to_string();
+                    let text = styler.named(&text, NamedStyle::Color(AnsiColors::Blue));
+                    write!(out, "{colored_prefix}{sep}{text}")
+                }
+                None => write!(out, "{colored_prefix}{sep}{text}"),
+            }
+        }
+        JsonValue::Bool(true) if config.bool_as_flag && !prefix.is_empty() => {
+            write!(out, "{colored_prefix}")
+        }
+        JsonValue::Bool(b) => {
+            let text = if *b { "true" } else { "false" };
+            match config.color_bool {
+                Some((style, _)) if *b => {
+                    write!(out, "{colored_prefix}{sep}{}", styler.named(text, style))
+                }
+                Some((_, style)) => {
+                    write!(out, "{colored_prefix}{sep}{}", styler.named(text, style))
+                }
+                None => write!(out, "{colored_prefix}{sep}{text}"),
             }
         }
-        JsonValue::Number(n) => write!(out, "{colored_prefix}{sep}{n}"),
-        JsonValue::Bool(b) => write!(out, "{colored_prefix}{sep}{b}"),
-        JsonValue::Null => write!(out, "{colored_prefix}{sep}null"),
+        JsonValue::Null => match config.color_null {
+            Some(style) => write!(out, "{colored_prefix}{sep}{}", styler.named("null", style)),
+            None => write!(out, "{colored_prefix}{sep}null"),
+        },
         JsonValue::Removed => Ok(()), // This won't be used since Removed values are skipped
+        JsonValue::Array(array)
+            if depth == 0
+                && config.pairs_fields.iter().any(|field| field == prefix)
+                && array.iter().all(|pair| as_pair(pair).is_some()) =>
+        {
+            let mut first = true;
+            for (key, value) in array.iter().filter_map(as_pair) {
+                if !first {
+                    write!(out, "{}", config.separator)?;
+                }
+                first = false;
+                display_value_recursive(out, value, key, depth, config, styler, deadline)?;
+            }
+            Ok(())
+        }
+        JsonValue::Object(_) | JsonValue::Array(_) if deadline_passed(deadline) => {
+            Err(render_timed_out())
+        }
         JsonValue::Object(map) => {
-            let prefix_braces = styler.depth_multi(prefix, "{", depth);
+            let open = if config.show_sizes {
+                let size = map.values().filter(|val| !is_hidden(val, config)).count();
+                Cow::Owned(format!("[{size}]{{"))
+            } else {
+                Cow::Borrowed("{")
+            };
+            if !map.is_empty() && config.max_depth.is_some_and(|max| depth >= max) {
+                let hidden = map.values().filter(|val| !is_hidden(val, config)).count();
+                let plural = if hidden == 1 { "" } else { "s" };
+                let collapsed = format!("{open}…{hidden} key{plural}}}");
+                return write!(out, "{}", styler.depth_multi(label, &collapsed, depth));
+            }
+            let prefix_braces = styler.depth_multi(label, &open, depth);
             write!(out, "{prefix_braces}")?;
             let mut first = true;
-            for (key, val) in map.iter() {
+            let (mut sorted, mut unsorted);
+            let entries: &mut dyn Iterator<Item = (&&str, &JsonValue)> = if config.sort_nested_keys
+            {
+                let mut entries: Vec<_> = map.iter().collect();
+                entries.sort_by_key(|(key, _)| **key);
+                sorted = entries.into_iter();
+                &mut sorted
+            } else {
+                unsorted = map.iter();
+                &mut unsorted
+            };
+            for (key, val) in entries {
+                if is_hidden(val, config) {
+                    continue;
+                }
                 if !first {
                     write!(out, " ")?;
                 } else {
                     first = false;
                 }
-                display_value_recursive(out, val, key, depth + 1, styler)?
+                let key = if config.trim_keys { key.trim() } else { key };
+                display_value_recursive(out, val, key, depth + 1, config, styler, deadline)?
             }
             let braces_end = styler.depth("}", depth);
             write!(out, "{braces_end}")?;
             Ok(())
         }
         JsonValue::Array(array) => {
-            let braces_start = styler.depth_multi(prefix, "[", depth);
+            // Elements taken by --message-pointer are skipped
+            let len = array
+                .iter()
+                .filter(|value| !matches!(value, JsonValue::Removed))
+                .count();
+            let open = if config.show_sizes {
+                Cow::Owned(format!("[{len}]["))
+            } else {
+                Cow::Borrowed("[")
+            };
+            if len > 0 && config.max_depth.is_some_and(|max| depth >= max) {
+                let plural = if len == 1 { "" } else { "s" };
+                let collapsed = format!("{open}…{len} item{plural}]");
+                return write!(out, "{}", styler.depth_multi(label, &collapsed, depth));
+            }
+            let braces_start = styler.depth_multi(label, &open, depth);
             let mut first = true;
             write!(out, "{braces_start}")?;
-            for value in array.iter() {
+            let shown = config.summarize_arrays.unwrap_or(len);
+            let elements = array
+                .iter()
+                .filter(|value| !matches!(value, JsonValue::Removed));
+            for value in elements.take(shown) {
                 if !first {
                     write!(out, " ")?;
                 } else {
                     first = false;
                 }
-                display_value_recursive(out, value, "", depth + 1, styler)?;
+                display_value_recursive(out, value, "", depth + 1, config, styler, deadline)?;
+            }
+            if len > shown {
+                if !first {
+                    write!(out, " ")?;
+                }
+                write!(out, "…(+{})", len - shown)?;
             }
             let braces_end = styler.depth("]", depth);
             write!(out, "{braces_end}")?;
@@ -333,25 +1815,206 @@ fn display_value_recursive(
     }
 }
 
+/// Whether the --render-timeout for a record has passed
+fn deadline_passed(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+fn render_timed_out() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        "rendering exceeded --render-timeout",
+    )
+}
+
+/// A string value cut to --max-value-bytes and --max-value-width, with the
+/// number of characters that were cut
+fn truncate_value<'s>(value: &'s str, config: &cfg::Config) -> (&'s str, usize) {
+    let mut end = value.len();
+    if let Some(max) = config.max_value_bytes.filter(|max| *max < end) {
+        end = max;
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+    }
+    if let Some((width_end, _)) = config
+        .max_value_width
+        .and_then(|width| value[..end].char_indices().nth(width))
+    {
+        end = width_end;
+    }
+    (&value[..end], value[end..].chars().count())
+}
+
+/// A top-level field, flattened if it's an object and --flatten-depth is set
+fn display_field(
+    out: &mut impl Write,
+    value: &JsonValue,
+    key: &str,
+    config: &cfg::Config,
+    styler: Styler,
+    deadline: Option<Instant>,
+) -> io::Result<()> {
+    match value {
+        JsonValue::Object(map)
+            if config.flatten_depth.is_some_and(|max| max > 0)
+                && has_visible_values(map, config) =>
+        {
+            display_flattened(out, map, key, 0, config, styler, deadline)
+        }
+        _ => display_value_recursive(out, value, key, 0, config, styler, deadline),
+    }
+}
+
+/// The fields of an object as dotted top-level fields, for --flatten-depth
+///
+/// Objects nested less than --flatten-depth deep are flattened as well. The
+/// fields are written like top-level fields, so options like --hex-field
+/// apply to their dotted paths. Objects without visible values are written in
+/// braces, so something is always written.
+fn display_flattened(
+    out: &mut impl Write,
+    map: &FnvIndexMap<&str, JsonValue>,
+    prefix: &str,
+    level: usize,
+    config: &cfg::Config,
+    styler: Styler,
+    deadline: Option<Instant>,
+) -> io::Result<()> {
+    let mut entries: Vec<_> = map
+        .iter()
+        .filter(|(_, val)| !is_hidden(val, config))
+        .collect();
+    if config.sort_nested_keys {
+        entries.sort_by_key(|(key, _)| **key);
+    }
+    let mut first = true;
+    for (key, val) in entries {
+        if !first {
+            write!(out, "{}", config.separator)?;
+        }
+        first = false;
+        let path = format!("{prefix}.{key}");
+        match val {
+            JsonValue::Object(nested)
+                if config.flatten_depth.is_some_and(|max| level + 1 < max)
+                    && has_visible_values(nested, config) =>
+            {
+                display_flattened(out, nested, &path, level + 1, config, styler, deadline)?
+            }
+            _ => display_value_recursive(out, val, &path, 0, config, styler, deadline)?,
+        }
+    }
+    Ok(())
+}
+
+/// Whether an object has any values that aren't hidden
+fn has_visible_values(map: &FnvIndexMap<&str, JsonValue>, config: &cfg::Config) -> bool {
+    map.values().any(|val| !is_hidden(val, config))
+}
+
+/// A path with its middle components replaced by `…`, for --shorten-paths
+///
+/// Returns `None` if `value` doesn't look like a path with components to
+/// remove. Values with whitespace or a `://` scheme are not paths.
+fn shorten_path(value: &str) -> Option<String> {
+    if value.contains(char::is_whitespace) || value.contains("://") {
+        return None;
+    }
+    let parts: Vec<&str> = value.split('/').collect();
+    // Keep the root of absolute paths and the first directory
+    let head = if value.starts_with('/') { 2 } else { 1 };
+    let tail = 2;
+    let empty_part = parts[1..].iter().any(|part| part.is_empty());
+    if empty_part || parts.len() <= head + tail + 1 {
+        return None;
+    }
+    let mut shortened = parts[..head].join("/");
+    shortened.push_str("/…/");
+    shortened.push_str(&parts[parts.len() - tail..].join("/"));
+    Some(shortened)
+}
+
+/// The key and value of a `[key, value]` array, for --pairs-field
+fn as_pair<'v, 'a>(value: &'v JsonValue<'a>) -> Option<(&'v str, &'v JsonValue<'a>)> {
+    match value {
+        JsonValue::Array(pair) => match pair.as_slice() {
+            [JsonValue::String(key), value] => Some((key, value)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::io::Cursor;
 
     fn test_config() -> cfg::Config {
-        cfg::Config {
-            no_key_fields: vec![
-                "timestamp".to_string(),
-                "level".to_string(),
-                "msg".to_string(),
-            ],
-            color: cfg::ColorOption::Never, // Disable color for testing simplicity
-            timestamp_format: cfg::TimestampFormat::Seconds,
-            timestamp_field: "timestamp".to_string(),
-            level_field: "level".to_string(),
-            millis_out_format: cfg::default_millis_out_format(),
-            secs_out_format: cfg::default_secs_out_format(),
+        test_config_with(&[])
+    }
+
+    /// The test config with additional command line arguments applied
+    ///
+    /// A default is left out when `args` sets the same flag, like
+    /// `--color=always`.
+    fn test_config_with(args: &[&str]) -> cfg::Config {
+        let defaults = [
+            ("--no-key-fields", "timestamp,level,msg"),
+            // Disable color for testing simplicity
+            ("--color", "never"),
+            ("--timestamp-format", "seconds"),
+        ];
+        let is_set = |flag: &str| {
+            args.iter()
+                .any(|arg| arg.split_once('=').map_or(*arg, |(name, _)| name) == flag)
+        };
+        let defaults = defaults
+            .iter()
+            .filter(|(flag, _)| !is_set(flag))
+            .map(|(flag, value)| format!("{flag}={value}"));
+        let args = std::iter::once("jsonlogprint".to_string())
+            .chain(defaults)
+            .chain(args.iter().map(|arg| arg.to_string()));
+        cfg::Config::new(cfg::Args::parse_from(args))
+    }
+
+    /// Run `f` and return the warnings it logged
+    fn capture_warnings(f: impl FnOnce()) -> String {
+        #[derive(Clone, Default)]
+        struct Captured(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
         }
+
+        let captured = Captured::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::WARN)
+            .with_ansi(false)
+            .with_writer({
+                let captured = captured.clone();
+                move || captured.clone()
+            })
+            .finish();
+        tracing::subscriber::with_default(subscriber, f);
+
+        let logged = captured.0.lock().unwrap().clone();
+        String::from_utf8(logged).unwrap()
+    }
+
+    /// Run `input` through `transform_lines` and return the output
+    fn render(input: &str, config: cfg::Config) -> String {
+        let mut output_cursor = Cursor::new(Vec::new());
+        transform_lines(Cursor::new(input), &mut output_cursor, config);
+        String::from_utf8(output_cursor.into_inner()).unwrap()
     }
 
     #[test]
@@ -453,4 +2116,1794 @@ mod tests {
         let output = String::from_utf8(output_cursor.into_inner()).unwrap();
         assert_eq!(input, output);
     }
+
+    #[test]
+    fn test_html_output_format() {
+        init_logging();
+        let input = r#"{"timestamp":1627494000,"level":"error","msg":"<b>bad</b>","a":{"b":1}}"#;
+
+        let config = test_config_with(&["--output-format=html", "--color=always"]);
+        let output = render(input, config);
+
+        assert!(output.starts_with("<pre>\n"), "{output}");
+        assert!(output.ends_with("</pre>\n"), "{output}");
+        assert!(
+            output.contains(r#"<span style="color:red">error</span>"#),
+            "{output}"
+        );
+        assert!(output.contains("&lt;b&gt;bad&lt;/b&gt;"), "{output}");
+        assert!(
+            output.contains(r#"<span style="color:blue">a{</span>"#),
+            "{output}"
+        );
+        assert!(!output.contains('\u{1b}'), "{output}");
+    }
+
+    #[test]
+    fn test_ansi_to_file_output_format() {
+        init_logging();
+        let input = r#"{"timestamp":1627494000,"level":"error","msg":"oops"}"#;
+        let path = std::env::temp_dir().join(format!(
+            "jsonlogprint-ansi-to-file-{}.log",
+            std::process::id()
+        ));
+
+        // --color=never is overridden, the whole point is to keep the escapes
+        let config = test_config_with(&[
+            "--output-format=ansi-to-file",
+            "--output",
+            path.to_str().unwrap(),
+        ]);
+        let (out, _) = open_output(&config).unwrap();
+        transform_lines(Cursor::new(input), out, config);
+
+        let output = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            "\u{1b}[2m2021-07-28T17:40:00Z\u{1b}[0m \u{1b}[31merror\u{1b}[0m oops\n",
+            output
+        );
+    }
+
+    #[test]
+    fn test_color_max_depth() {
+        init_logging();
+        let input = r#"{"level":"info","a":{"b":{"c":{"d":1}}}}"#;
+
+        let config = test_config_with(&["--color=always", "--color-max-depth=1"]);
+        let output = render(input, config);
+
+        let expected = "\u{1b}[36minfo\u{1b}[0m \u{1b}[34ma{\u{1b}[0m\u{1b}[36mb{\u{1b}[0mc{d=1}\u{1b}[36m}\u{1b}[0m\u{1b}[34m}\u{1b}[0m\n";
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_compiled_time_format_is_reused() {
+        init_logging();
+        let input = r#"{"timestamp":1627494000123,"msg":"one"}
+{"timestamp":1627494001456,"msg":"two"}
+{"timestamp":1627494002789,"msg":"three"}"#;
+        let expected = "17:40:00.123 one\n17:40:01.456 two\n17:40:02.789 three\n";
+
+        // The format is compiled once into the config, which is all that
+        // rendering uses
+        let config =
+            test_config_with(&["--timestamp-format=millis", "--ts-out-format=%H:%M:%S%.3f"]);
+        assert_eq!(
+            cfg::parse_time_format("%H:%M:%S%.3f").unwrap(),
+            config.millis_out_format
+        );
+
+        assert_eq!(expected, render(input, config));
+    }
+
+    #[test]
+    fn test_invalid_time_format() {
+        assert!(cfg::parse_time_format("%Y-%").is_err());
+    }
+
+    #[test]
+    fn test_negative_seconds_timestamp() {
+        init_logging();
+        let input = r#"{"timestamp":-86400,"msg":"seconds"}
+{"timestamp":-1000000000,"msg":"auto"}"#;
+        let expected = "1969-12-31T00:00:00Z seconds\n1938-04-24T22:13:20Z auto\n";
+
+        let config = test_config_with(&["--timestamp-format=auto"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_negative_millis_timestamp() {
+        init_logging();
+        let input = r#"{"timestamp":-1500,"msg":"millis"}"#;
+        let config = test_config_with(&["--timestamp-format=millis"]);
+        assert_eq!("1969-12-31T23:59:58.500Z millis\n", render(input, config));
+
+        let input = r#"{"timestamp":-1000000000001,"msg":"auto"}"#;
+        let config = test_config_with(&["--timestamp-format=auto"]);
+        assert_eq!("1938-04-24T22:13:19.999Z auto\n", render(input, config));
+    }
+
+    #[test]
+    fn test_subsecond_precision() {
+        init_logging();
+        let input = r#"{"timestamp":1627494000123,"msg":"hi"}"#;
+
+        let config = test_config_with(&["--timestamp-format=millis", "--subsecond=0"]);
+        assert_eq!("2021-07-28T17:40:00Z hi\n", render(input, config));
+
+        let config = test_config_with(&["--timestamp-format=millis", "--subsecond=6"]);
+        assert_eq!("2021-07-28T17:40:00.123000Z hi\n", render(input, config));
+
+        let input = r#"{"timestamp":1627494000,"msg":"hi"}"#;
+        let config = test_config_with(&["--subsecond=3"]);
+        assert_eq!("2021-07-28T17:40:00.000Z hi\n", render(input, config));
+
+        assert!(cfg::Args::try_parse_from(["jsonlogprint", "--subsecond=2"]).is_err());
+    }
+
+    #[test]
+    fn test_message_pointer() {
+        init_logging();
+        let input =
+            r#"{"timestamp":1627494000,"level":"info","fields":{"message":"hello there","x":1}}"#;
+        let expected = "2021-07-28T17:40:00Z info hello there fields{x=1}\n";
+
+        let config = test_config_with(&["--message-pointer=/fields/message"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_message_pointer_into_array() {
+        let input = r#"{"level":"info","events":[{"n":1},"hello there",{"n":2}]}"#;
+
+        let config = test_config_with(&["--message-pointer=/events/1"]);
+        assert_eq!(
+            "info hello there events[{n=1} {n=2}]\n",
+            render(input, config)
+        );
+
+        let config = test_config_with(&["--message-pointer=/events/1", "--show-sizes"]);
+        assert_eq!(
+            "info hello there events[2][[1]{n=1} [1]{n=2}]\n",
+            render(input, config)
+        );
+    }
+
+    #[test]
+    fn test_summarize_arrays() {
+        init_logging();
+        let input = r#"{"level":"info","nums":[1,2,3,4,5,6,7,8,9,10],"few":[1,2,3]}"#;
+        let expected = "info nums[1 2 3 …(+7)] few[1 2 3]\n";
+
+        let config = test_config_with(&["--summarize-arrays=3"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_no_key_fields_only_if_first() {
+        init_logging();
+        let input = r#"{"level":"info","msg":"hi","user":"bob"}
+{"timestamp":1627494000,"user":"bob","level":"warn","msg":"late"}
+{"user":"bob","msg":"last"}"#;
+        let expected = "info hi user=bob\n\
+2021-07-28T17:40:00Z user=bob level=warn msg=late\n\
+user=bob msg=last\n";
+
+        let config = test_config_with(&["--no-key-fields-only-if-first"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_component_field() {
+        init_logging();
+        let input = r#"{"level":"info","msg":"hi","component":"db"}"#;
+        let config = test_config_with(&["--color=always", "--component-field=component"]);
+        assert_eq!(
+            "\u{1b}[36minfo\u{1b}[0m hi \u{1b}[34mcomponent\u{1b}[0m=\u{1b}[35mdb\u{1b}[0m\n",
+            render(input, config)
+        );
+
+        let input = r#"{"level":"info","component":"http","msg":"hi"}"#;
+        let mut config = test_config_with(&["--color=always", "--component-field=component"]);
+        config.no_key_fields = vec![
+            "level".to_string(),
+            "component".to_string(),
+            "msg".to_string(),
+        ];
+        assert_eq!(
+            "\u{1b}[36minfo\u{1b}[0m \u{1b}[35mhttp\u{1b}[0m hi\n",
+            render(input, config)
+        );
+    }
+
+    #[test]
+    fn test_expand_scientific() {
+        init_logging();
+        let input = r#"{"level":"info","a":1e3,"b":1.5e-3,"c":2.5e21,"d":7}"#;
+
+        let config = test_config_with(&["--expand-scientific"]);
+        assert_eq!("info a=1000 b=0.0015 c=2.5e21 d=7\n", render(input, config));
+
+        let config = test_config();
+        assert_eq!(
+            "info a=1000.0 b=0.0015 c=2.5e21 d=7\n",
+            render(input, config)
+        );
+    }
+
+    #[test]
+    fn test_errors_to_separate_stream() {
+        init_logging();
+        let input = r#"{"level":"info","msg":"fine"}
+not json
+{"level":"ERROR","msg":"bad"}
+{"level":"crit","msg":"worse"}
+{"level":"warn","msg":"meh"}"#;
+
+        let mut out = Vec::new();
+        let mut errors = Vec::new();
+        let config = test_config_with(&["--errors-to=stderr"]);
+        transform_lines(
+            Cursor::new(input),
+            OutputSink::with_errors(&mut out, &mut errors),
+            config,
+        );
+
+        assert_eq!(
+            "info fine\nnot json\nwarn meh\n",
+            String::from_utf8(out).unwrap()
+        );
+        assert_eq!(
+            "ERROR bad\ncrit worse\n",
+            String::from_utf8(errors).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_line_numbers() {
+        init_logging();
+        let input = r#"{"level":"info","msg":"one"}
+not json
+{"level":"warn","msg":"three"}"#;
+        let expected = "   1 info one\n   2 not json\n   3 warn three\n";
+
+        let config = test_config_with(&["--line-numbers"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_highlight_new_errors() {
+        init_logging();
+        let input = r#"{"level":"error","msg":"disk full"}
+{"level":"info","msg":"retrying"}
+{"level":"error","msg":"disk full"}
+{"level":"error","msg":"disk on fire"}"#;
+        let expected = "\u{1b}[31merror\u{1b}[0m \u{1b}[1mdisk full\u{1b}[0m\n\
+\u{1b}[36minfo\u{1b}[0m retrying\n\
+\u{1b}[31merror\u{1b}[0m disk full\n\
+\u{1b}[31merror\u{1b}[0m \u{1b}[1mdisk on fire\u{1b}[0m\n";
+
+        let config = test_config_with(&["--color=always", "--highlight-new-errors"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_single_quote_style() {
+        init_logging();
+        let input = r#"{"level":"info","a":"it's here","b":"say\"hi\"","c":"x\\y"}"#;
+        let expected = r#"info a='it\'s here' b=say"hi" c='x\\y'"#;
+
+        let config = test_config_with(&["--quote-style=single"]);
+        let output = render(input, config);
+
+        assert_eq!(format!("{expected}\n"), output);
+    }
+
+    #[test]
+    fn test_trim_keys() {
+        init_logging();
+        let input = r#"{" level ":"info","msg  ":"hi","nested":{" a":1}}"#;
+
+        let config = test_config_with(&["--trim-keys"]);
+        assert_eq!("info hi nested{a=1}\n", render(input, config));
+    }
+
+    #[test]
+    fn test_uptime_timestamp() {
+        init_logging();
+        let input = r#"{"timestamp":3723.5,"msg":"booted"}
+{"timestamp":90000,"msg":"a day later"}"#;
+        let expected = "+01:02:03.500 booted\n+25:00:00.000 a day later\n";
+
+        let config = test_config_with(&["--timestamp-format=uptime"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_auto_detect() {
+        init_logging();
+        let input = r#"starting up
+{"time":1627494000123,"severity":"INFO","message":"one","n":1}
+{"time":1627494001456,"severity":"WARN","message":"two","n":2}"#;
+        let expected = "starting up\n\
+2021-07-28T17:40:00.123Z INFO one n=1\n\
+2021-07-28T17:40:01.456Z WARN two n=2\n";
+
+        let config = test_config_with(&["--auto-detect"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_hash_color_field() {
+        init_logging();
+        let input = r#"{"msg":"a","request_id":"req-1"}
+{"msg":"b","request_id":"req-1"}
+{"msg":"c","request_id":"req-2"}"#;
+
+        let config = test_config_with(&["--color=always", "--hash-color-field=request_id"]);
+        let output = render(input, config);
+
+        let colors: Vec<&str> = output
+            .lines()
+            .map(|line| {
+                let value = line.split('=').nth(1).unwrap();
+                assert!(value.starts_with('\u{1b}'), "{line:?}");
+                value.split('m').next().unwrap()
+            })
+            .collect();
+        assert_eq!(colors[0], colors[1]);
+        assert_ne!(colors[0], colors[2]);
+    }
+
+    #[test]
+    fn test_spacing() {
+        init_logging();
+        let input = r#"{"level":"info","msg":"one"}
+{"level":"info","msg":"two"}
+not json
+{"level":"info","msg":"three"}"#;
+        let expected = "info one\n\ninfo two\n\nnot json\ninfo three\n\n";
+
+        let config = test_config_with(&["--spacing"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_compact_timestamp() {
+        init_logging();
+        let input = r#"{"timestamp":1627494000,"msg":"one"}
+{"timestamp":1627494001,"msg":"two"}
+{"timestamp":1627580400,"msg":"next day"}
+{"timestamp":1627580401,"msg":"four"}"#;
+        let expected = "2021-07-28T17:40:00Z one\n\
+17:40:01 two\n\
+2021-07-29T17:40:00Z next day\n\
+17:40:01 four\n";
+
+        let config = test_config_with(&["--compact-timestamp"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+
+        let args = [
+            "jsonlogprint",
+            "--compact-timestamp",
+            "--ts-out-format=%H:%M",
+        ];
+        assert!(cfg::Args::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn test_auto_humanize() {
+        init_logging();
+        let input = r#"{"msg":"done","latency_ms":1500,"db_ns":250,"total_sec":3723.5,"wait_us":12.25,"count":5,"slow_ms":120000}"#;
+        let expected =
+            "done latency_ms=1.5s db_ns=250ns total_sec=1h2m3.5s wait_us=12.25µs count=5 slow_ms=2m\n";
+
+        let config = test_config_with(&["--auto-humanize"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_stream_render() {
+        init_logging();
+        let input = r#"{"user":"bob","timestamp":1627494000,"msg":"streamed","nested":{"a":1},"level":"warn"}
+{"msg":"truncated","user":"#;
+        let expected = "user=bob 2021-07-28T17:40:00Z streamed nested{a=1} warn\n\
+truncated\n\
+{\"msg\":\"truncated\",\"user\":\n";
+
+        let config = test_config_with(&["--stream-render"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_stream_render_conflicts() {
+        let parse = |arg| cfg::Args::try_parse_from(["jsonlogprint", "--stream-render", arg]);
+
+        for arg in [
+            "--exclude-fields=span_id",
+            "--only-fields=msg",
+            "--field-default=env=prod",
+            "--template={msg}",
+            "--hide-info-level",
+        ] {
+            assert!(parse(arg).is_err(), "{arg} should conflict");
+        }
+        assert!(parse("--output-format=json").unwrap().check().is_err());
+        assert!(parse("--output-format=html").unwrap().check().is_ok());
+    }
+
+    #[test]
+    fn test_color_test() {
+        init_logging();
+        let config = test_config_with(&["--color-test"]);
+        let output = render(&color_test_input(&config), config);
+
+        for level in ["trace", "debug", "info", "warn", "error", "critical"] {
+            assert!(output.contains(&format!(" {level} a sample {level} message\n")));
+        }
+        assert!(output.contains("depth0{depth1{depth2{"));
+    }
+
+    #[test]
+    fn test_sort_by_value() {
+        init_logging();
+        let input =
+            r#"{"msg":"metrics","requests":20,"name":"web","errors":3,"bytes":1.5e3,"host":"a"}"#;
+        let expected = "metrics bytes=1500.0 requests=20 errors=3 host=a name=web\n";
+
+        let config = test_config_with(&["--sort-by-value"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_separator() {
+        init_logging();
+        let input = r#"{"timestamp":1627494000,"level":"info","msg":"Tabs","user":"bob","req":{"id":1,"ok":true}}"#;
+        let expected = "2021-07-28T17:40:00Z info Tabs\tuser=bob\treq{id=1 ok=true}\n";
+
+        let config = test_config_with(&["--separator", r"\t"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+
+        // A trailing timestamp is a field like any other
+        let expected = "info Tabs\tuser=bob\treq{id=1 ok=true}\t2021-07-28T17:40:00Z\n";
+        let config = test_config_with(&["--separator", r"\t", "--timestamp-position=end"]);
+        assert_eq!(expected, render(input, config));
+    }
+
+    #[test]
+    fn test_split_by_level() {
+        init_logging();
+        let input = r#"{"timestamp":1627494000,"level":"info","msg":"first"}
+{"timestamp":1627494001,"level":"ERROR","msg":"broken"}
+not json
+{"timestamp":1627494002,"level":"info","msg":"second"}"#;
+        let dir = std::env::temp_dir().join(format!("jlp-split-{}", std::process::id()));
+
+        let config = test_config();
+        let sink = OutputSink::split_by_level(dir.clone());
+        transform_lines(Cursor::new(input), sink, config);
+
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(
+            read("info.log"),
+            "2021-07-28T17:40:00Z info first\n2021-07-28T17:40:02Z info second\n"
+        );
+        assert_eq!(read("error.log"), "2021-07-28T17:40:01Z ERROR broken\n");
+        assert_eq!(read("other.log"), "not json\n");
+        assert!(!dir.join("warn.log").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_start_and_stop_after() {
+        init_logging();
+        let input = r#"{"timestamp":1627494000,"level":"info","msg":"setup"}
+=== BEGIN TEST ===
+{"timestamp":1627494001,"level":"info","msg":"running"}
+{"timestamp":1627494002,"level":"info","msg":"test passed"}
+{"timestamp":1627494003,"level":"info","msg":"teardown"}"#;
+        let expected = "2021-07-28T17:40:01Z info running\n\
+2021-07-28T17:40:02Z info test passed\n";
+
+        let config = test_config_with(&[
+            "--start-after",
+            "BEGIN TEST",
+            "--stop-after",
+            "test (passed|failed)",
+        ]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_head() {
+        init_logging();
+        let input = "one\ntwo\nthree\n";
+
+        let output = render(input, test_config_with(&["--head", "2"]));
+        assert_eq!("one\ntwo\n", output);
+
+        let output = render(input, test_config_with(&["--head", "0"]));
+        assert_eq!("", output);
+    }
+
+    #[test]
+    fn test_tail() {
+        init_logging();
+        let input = r#"{"timestamp":1627494000,"level":"info","msg":"one"}
+{"timestamp":1627494001,"level":"info","msg":"two"}
+three
+{"timestamp":1627494003,"level":"info","msg":"four"}
+{"timestamp":1627494004,"level":"info","msg":"five"}"#;
+        let expected = "2021-07-28T17:40:03Z info four\n\
+2021-07-28T17:40:04Z info five\n";
+
+        let config = test_config_with(&["--tail", "2"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_invert_grep() {
+        init_logging();
+        let input = r#"{"timestamp":1627494000,"level":"info","msg":"GET /healthz"}
+{"timestamp":1627494001,"level":"info","msg":"GET /users"}
+not json /healthz"#;
+        let expected = "2021-07-28T17:40:01Z info GET /users\n";
+
+        let config = test_config_with(&["--grep", "/healthz", "--invert"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_invert_level_filter() {
+        init_logging();
+        let input = r#"{"timestamp":1627494000,"level":"debug","msg":"noise"}
+{"timestamp":1627494001,"level":"error","msg":"broken"}
+{"timestamp":1627494002,"level":"info","msg":"fine"}"#;
+
+        let config = test_config_with(&["--min-level", "warn"]);
+        let output = render(input, config);
+        assert_eq!("2021-07-28T17:40:01Z error broken\n", output);
+
+        let config = test_config_with(&["--min-level", "warn", "--invert"]);
+        let output = render(input, config);
+        let expected = "2021-07-28T17:40:00Z debug noise\n\
+2021-07-28T17:40:02Z info fine\n";
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_invert_level_filter_keeps_records_without_level() {
+        let input = r#"{"level":"debug","msg":"noise"}
+{"level":"error","msg":"broken"}
+{"msg":"no level"}
+{"level":"verbose","msg":"unknown level"}
+not json"#;
+
+        let config = test_config_with(&["--min-level", "warn", "--invert"]);
+        let output = render(input, config);
+
+        assert_eq!(
+            "debug noise\nno level\nverbose unknown level\nnot json\n",
+            output
+        );
+    }
+
+    #[test]
+    fn test_max_depth_counts_hidden() {
+        init_logging();
+        let input =
+            r#"{"msg":"deep","nested":{"a":1,"b":{"c":2},"d":[1,2]},"list":[{"x":1}],"top":true}"#;
+        let expected = "deep nested{…3 keys} list[…1 item] top=true\n";
+
+        let config = test_config_with(&["--max-depth", "0"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_prefix() {
+        init_logging();
+        let input = r#"{"timestamp":1627494000,"level":"info","msg":"json","text":"two\nlines"}
+not json"#;
+        let expected = "[api] 2021-07-28T17:40:00Z info json\n\
+[api] text=two\n\
+[api] lines\n\
+[api] not json\n";
+
+        let config = test_config_with(&["--prefix", "[api]"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_nested_level_field() {
+        init_logging();
+        let input = r#"{"msg":"nested","meta":{"level":"error","host":"a"}}
+{"msg":"flat","meta.level":"warn"}"#;
+        let expected = "\u{1b}[31merror\u{1b}[0m nested \
+\u{1b}[34mmeta{\u{1b}[0m\u{1b}[36mhost\u{1b}[0m=a\u{1b}[34m}\u{1b}[0m\n\
+\u{1b}[33mwarn\u{1b}[0m flat\n";
+
+        let mut config = test_config_with(&["--color=always", "--level-field=meta.level"]);
+        config.no_key_fields = vec!["meta.level".to_string(), "msg".to_string()];
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_lossy() {
+        init_logging();
+        let input: &[u8] = b"{\"level\":\"info\",\"msg\":\"caf\xe9\"}\r\nplain \xff\n";
+
+        let mut output = Vec::new();
+        transform_lines(input, &mut output, test_config());
+        assert_eq!("\n\n", String::from_utf8(output).unwrap());
+
+        let mut output = Vec::new();
+        transform_lines(input, &mut output, test_config_with(&["--lossy"]));
+        assert_eq!(
+            "info caf\u{fffd}\nplain \u{fffd}\n",
+            String::from_utf8(output).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_min_level_ignores_case() {
+        init_logging();
+        let input = r#"{"timestamp":1627494000,"level":"info","msg":"hidden"}
+{"timestamp":1627494001,"level":"warn","msg":"shown"}
+{"timestamp":1627494002,"level":"Error","msg":"also shown"}"#;
+        let expected = "2021-07-28T17:40:01Z warn shown\n\
+2021-07-28T17:40:02Z Error also shown\n";
+
+        let config = test_config_with(&["--min-level", "WARN"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_timestamp_position_end() {
+        init_logging();
+        let input = r#"{"timestamp":1627494000,"level":"info","msg":"Message first","user":"bob"}"#;
+        let expected = "info Message first user=bob 2021-07-28T17:40:00Z\n";
+
+        let config = test_config_with(&["--timestamp-position=end"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_keep_raw() {
+        init_logging();
+        let input = r#"{"timestamp":1627494000,"level":"info","msg":"hi","user":{"id":1}}
+not json"#;
+        let expected = "2021-07-28T17:40:00Z info hi user{id=1}\n    \
+{\"timestamp\":1627494000,\"level\":\"info\",\"msg\":\"hi\",\"user\":{\"id\":1}}\n\
+not json\n";
+
+        let config = test_config_with(&["--keep-raw"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_warn_type_mismatch() {
+        let input = r#"{"timestamp":1627494000,"level":{"name":"info"},"msg":"hi"}
+{"timestamp":[1],"level":"info","msg":"hi"}"#;
+
+        let logged = capture_warnings(|| {
+            render(input, test_config_with(&["--warn-type-mismatch"]));
+        });
+
+        assert!(logged.contains("level field is not a string field=level found=object"));
+        assert!(logged.contains("timestamp field is not a number or string"));
+    }
+
+    #[test]
+    fn test_quote_header() {
+        init_logging();
+        let input =
+            r#"{"timestamp":1627494000,"level":"info","msg":"a \"quoted\" message","user":"bob"}"#;
+        let expected = "2021-07-28T17:40:00Z info \"a \\\"quoted\\\" message\" user=bob\n";
+
+        let config = test_config_with(&["--quote-header"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_lock_timestamp_unit() {
+        init_logging();
+        let input = r#"{"timestamp":1627494000123,"level":"info","msg":"one"}
+{"timestamp":1627494001456,"level":"info","msg":"two"}
+{"timestamp":1627494002789,"level":"info","msg":"three"}"#;
+
+        let auto = render(input, test_config_with(&["--timestamp-format=auto"]));
+        let locked = render(
+            input,
+            test_config_with(&["--timestamp-format=auto", "--lock-timestamp-unit"]),
+        );
+
+        assert_eq!(auto, locked);
+        assert!(locked.starts_with("2021-07-28T17:40:00.123Z info one\n"));
+    }
+
+    #[test]
+    fn test_filter_and() {
+        init_logging();
+        let input = r#"{"level":"error","msg":"server error","status":503}
+{"level":"error","msg":"client error","status":404}
+{"level":"info","msg":"ok","status":500}
+not json"#;
+        let expected = "error server error status=503\n";
+
+        let config = test_config_with(&["--filter", r#"level == "error" && status >= 500"#]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_filter_or() {
+        init_logging();
+        let input = r#"{"level":"info","msg":"slow","latency":{"ms":2500}}
+{"level":"info","msg":"fast","latency":{"ms":20}}
+{"level":"warn","msg":"retrying"}"#;
+        let expected = "info slow latency{ms=2500}\nwarn retrying\n";
+
+        let config = test_config_with(&["--filter", "latency.ms > 1000 || level == 'warn'"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_max_fields_shown() {
+        init_logging();
+        let input = r#"{"level":"info","msg":"busy","a":1,"b":2,"c":3,"d":4,"e":5}"#;
+        let expected = "info busy a=1 b=2 …(+3 more)\n";
+
+        let config = test_config_with(&["--max-fields-shown", "2"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_render_timeout() {
+        init_logging();
+        let wide: String = (0..1000)
+            .map(|i| format!(r#""k{i}":{{"a":[1,2,{{"b":{{"c":[3,4]}}}}]}}"#))
+            .collect::<Vec<_>>()
+            .join(",");
+        let input = format!(r#"{{"level":"info","msg":"huge",{wide}}}"#);
+
+        let output = render(&input, test_config_with(&["--render-timeout", "0"]));
+        assert_eq!(format!("{input}\n"), output);
+
+        let output = render(&input, test_config_with(&["--render-timeout", "60000"]));
+        assert!(output.starts_with("info huge k0{a[1 2 {b{c[3 4]}}]} k1{"));
+    }
+
+    #[test]
+    fn test_render_timeout_flat_record() {
+        let flat: String = (0..1000)
+            .map(|i| format!(r#""k{i}":"{}""#, "x".repeat(100)))
+            .collect::<Vec<_>>()
+            .join(",");
+        let input = format!(r#"{{"level":"info","msg":"flat",{flat}}}"#);
+
+        let output = render(&input, test_config_with(&["--render-timeout", "0"]));
+        assert_eq!(format!("{input}\n"), output);
+    }
+
+    #[test]
+    fn test_empty_placeholder() {
+        init_logging();
+        let input = "{}\n{ }\n{\"msg\":\"not empty\"}";
+
+        let output = render(input, test_config());
+        assert_eq!("\n\nnot empty\n", output);
+
+        let output = render(input, test_config_with(&["--empty-placeholder", "(empty)"]));
+        assert_eq!("(empty)\n(empty)\nnot empty\n", output);
+
+        // JSON output stays valid JSON
+        let config = test_config_with(&["--empty-placeholder", "(empty)", "--output-format=json"]);
+        let output = render(input, config);
+        assert_eq!("{}\n{}\n{\"msg\":\"not empty\"}\n", output);
+    }
+
+    #[test]
+    fn test_combine_consecutive_json() {
+        init_logging();
+        let input = r#"{
+  "level": "info",
+  "msg": "spread {out}"
+}
+plain
+{"level":"warn","msg":"one line"}"#;
+        let expected = "info spread {out}\nplain\nwarn one line\n";
+
+        let config = test_config_with(&["--combine-consecutive-json"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_gradient() {
+        init_logging();
+        let input = r#"{"msg":"fast","latency":50}
+{"msg":"slow","latency":5000}
+{"msg":"middling","latency":550}"#;
+
+        let config = test_config_with(&["--color=always", "--gradient", "latency:100:1000"]);
+        let output = render(input, config);
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[0].ends_with("=\u{1b}[38;2;0;200;0m50\u{1b}[0m"));
+        assert!(lines[1].ends_with("=\u{1b}[38;2;220;0;0m5000\u{1b}[0m"));
+        assert!(lines[2].ends_with("=\u{1b}[38;2;220;200;0m550\u{1b}[0m"));
+    }
+
+    #[test]
+    fn test_warn_duplicate_keys() {
+        let input = r#"{"msg":"fine","user":"alice"}
+{"msg":"dup","user":"alice","user":"bob"}"#;
+        let mut output = String::new();
+
+        let logged = capture_warnings(|| {
+            output = render(input, test_config_with(&["--warn-duplicate-keys"]));
+        });
+
+        assert_eq!("fine user=alice\ndup user=bob\n", output);
+        assert!(logged.contains("duplicate key, the earlier value was dropped line=2 key=user"));
+        assert_eq!(logged.matches("duplicate key").count(), 1);
+    }
+
+    #[test]
+    fn test_line_numbers_count_joined_lines() {
+        let input = r#"{
+  "msg": "pretty"
+}
+plain
+{"msg":"dup","user":"alice","user":"bob"}"#;
+        let mut output = String::new();
+
+        let logged = capture_warnings(|| {
+            let config =
+                test_config_with(&["--multiline", "--line-numbers", "--warn-duplicate-keys"]);
+            output = render(input, config);
+        });
+
+        assert_eq!("   1 pretty\n   4 plain\n   5 dup user=bob\n", output);
+        assert!(logged.contains("line=5 key=user"));
+    }
+
+    #[test]
+    fn test_passthrough_prefix() {
+        let input = r#"starting up
+{"msg":"ready","port":8080}
+{"msg": broken"#;
+
+        let output = render(input, test_config_with(&["--passthrough-prefix", "│ "]));
+
+        assert_eq!(
+            "│ starting up\nready port=8080\n│ {\"msg\": broken\n",
+            output
+        );
+    }
+
+    #[test]
+    fn test_field_default() {
+        let input = r#"{"msg":"one","env":"staging"}
+{"msg":"two"}"#;
+
+        let output = render(input, test_config_with(&["--field-default", "env=prod"]));
+
+        assert_eq!("one env=staging\ntwo env=prod\n", output);
+
+        let config =
+            test_config_with(&["--field-default", "env=prod", "--template", "{msg}:{env}"]);
+        assert_eq!("one:staging\ntwo:prod\n", render(input, config));
+
+        let config =
+            test_config_with(&["--field-default", "env=prod", "--filter", "env == 'prod'"]);
+        assert_eq!("two env=prod\n", render(input, config));
+
+        let config = test_config_with(&["--field-default", "env=prod", "--output-format=json"]);
+        assert_eq!(
+            "{\"msg\":\"one\",\"env\":\"staging\"}\n{\"msg\":\"two\",\"env\":\"prod\"}\n",
+            render(input, config)
+        );
+    }
+
+    #[test]
+    fn test_label() {
+        let input = r#"{"msg":"done","duration_ms":1500,"meta":{"duration_ms":3}}"#;
+
+        let output = render(
+            input,
+            test_config_with(&["--label", "duration_ms=⏱", "--label", "meta=📎"]),
+        );
+
+        assert_eq!("done ⏱=1500 📎{duration_ms=3}\n", output);
+    }
+
+    #[test]
+    fn test_error_format_json() {
+        let input = r#"{"msg":"fine"}
+{"msg": broken
+not json"#;
+
+        let output = render(input, test_config_with(&["--error-format", "json"]));
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "fine");
+        let error: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+        assert_eq!(error["line"], r#"{"msg": broken"#);
+        assert!(error["jlp_error"]
+            .as_str()
+            .unwrap()
+            .starts_with("expected value at line 1"));
+        assert_eq!(lines[2], "not json");
+    }
+
+    #[test]
+    fn test_pairs_field() {
+        let input = r#"{"msg":"req","headers":[["k1","v1"],["k2","v2"]],"other":[["k3","v3"]]}
+{"msg":"odd","headers":[["k1","v1"],["k2"]]}"#;
+
+        let output = render(input, test_config_with(&["--pairs-field", "headers"]));
+
+        assert_eq!(
+            "req k1=v1 k2=v2 other[[k3 v3]]\nodd headers[[k1 v1] [k2]]\n",
+            output
+        );
+    }
+
+    #[test]
+    fn test_dim_when() {
+        let input = r#"{"msg":"checked","path":"/healthz","status":200}
+{"msg":"created","path":"/users","status":201}"#;
+
+        let config = test_config_with(&["--color=always", "--dim-when", r#"path == "/healthz""#]);
+        let output = render(input, config);
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[0],
+            "\u{1b}[2mchecked path=/healthz status=200\u{1b}[0m"
+        );
+        assert_eq!(
+            lines[1],
+            "created \u{1b}[34mpath\u{1b}[0m=/users \u{1b}[34mstatus\u{1b}[0m=201"
+        );
+    }
+
+    #[test]
+    fn test_count_lines() {
+        let input = r#"{"msg":"one","level":"info"}
+starting up
+{"msg":"two","level":"debug"}
+{"msg": broken
+{"msg":"three","level":"error"}"#;
+
+        let output = render(input, test_config_with(&["--count-lines"]));
+        assert_eq!("records=3 passthrough=1 errors=1\n", output);
+
+        let config = test_config_with(&["--count-lines", "--min-level=info"]);
+        let output = render(input, config);
+        assert_eq!("records=2 passthrough=1 errors=1\n", output);
+    }
+
+    #[test]
+    fn test_header_order_json() {
+        let input = r#"{"msg":"started","level":"info","timestamp":1627494000,"user":"alice"}"#;
+
+        let output = render(input, test_config());
+        assert_eq!("2021-07-28T17:40:00Z info started user=alice\n", output);
+
+        let output = render(input, test_config_with(&["--header-order", "json"]));
+        assert_eq!("started info 2021-07-28T17:40:00Z user=alice\n", output);
+    }
+
+    #[test]
+    fn test_max_value_bytes() {
+        let input = r#"{"msg":"upload","blob":"aGVsbG8gd29ybGQsIHRoaXMgaXMgYSBsb25nIGJsb2I=","name":"héllo","n":1234567890}"#;
+
+        let output = render(input, test_config_with(&["--max-value-bytes", "8"]));
+        assert_eq!(
+            "upload blob=aGVsbG8g…(36 more) name=héllo n=1234567890\n",
+            output
+        );
+
+        // `é` is two bytes, so it is cut before rather than split
+        let output = render(input, test_config_with(&["--max-value-bytes", "2"]));
+        assert_eq!(
+            "upload blob=aG…(42 more) name=h…(4 more) n=1234567890\n",
+            output
+        );
+    }
+
+    #[test]
+    fn test_max_value_bytes_and_width() {
+        let input = r#"{"msg":"both","name":"ééééé12345"}"#;
+
+        // The tighter limit wins, and the count is of what's left after it
+        let config = test_config_with(&["--max-value-bytes=8", "--max-value-width=3"]);
+        assert_eq!("both name=ééé…(7 more)\n", render(input, config));
+
+        let config = test_config_with(&["--max-value-bytes=8", "--max-value-width=6"]);
+        assert_eq!("both name=éééé…(6 more)\n", render(input, config));
+    }
+
+    #[test]
+    fn test_show_changes_for() {
+        let input = r#"{"msg":"one","status":"ok","n":1}
+{"msg":"two","status":"ok","n":1}
+{"msg":"three","n":1}
+{"msg":"four","status":"ok","n":1}
+{"msg":"five","status":"degraded","n":1}"#;
+
+        let output = render(input, test_config_with(&["--show-changes-for", "status"]));
+
+        let expected =
+            "one status=ok n=1\ntwo n=1\nthree n=1\nfour n=1\nfive status=degraded n=1\n";
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_show_changes_for_array_diff() {
+        let input = r#"{"msg":"one","tags":["a","b"]}
+{"msg":"two","tags":["a","b","c"]}
+{"msg":"three","tags":["a","c"]}"#;
+
+        let output = render(input, test_config_with(&["--show-changes-for", "tags"]));
+        assert_eq!(
+            "one tags[a b]\ntwo tags[a b +c]\nthree tags[a c -b]\n",
+            output
+        );
+
+        let config = test_config_with(&["--color=always", "--show-changes-for", "tags"]);
+        let output = render(input, config);
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[1].ends_with("b \u{1b}[32m+c\u{1b}[0m\u{1b}[34m]\u{1b}[0m"));
+        assert!(lines[2].ends_with("c \u{1b}[31m-b\u{1b}[0m\u{1b}[34m]\u{1b}[0m"));
+    }
+
+    #[test]
+    fn test_template() {
+        let input = r#"{"timestamp":"12:00:01","level":"info","msg":"started","request_id":"r-1"}
+{"timestamp":"12:00:02","level":"warn","msg":"slow","extra":true}"#;
+
+        let template = "{timestamp} [{level}] {msg} ({request_id}) {{done}}";
+        let output = render(input, test_config_with(&["--template", template]));
+
+        let expected = "12:00:01 [info] started (r-1) {done}\n12:00:02 [warn] slow () {done}\n";
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_template_nested_fields() {
+        let input = r#"{"msg":"bought","context":{"request_id":"r-1"},"items":["apple","pear"]}"#;
+
+        let template = "{msg} {items.0} {items.1} {items.2}for {context.request_id}{context.user}";
+        let output = render(input, test_config_with(&["--template", template]));
+
+        assert_eq!("bought apple pear for r-1\n", output);
+    }
+
+    #[test]
+    fn test_template_styles() {
+        let input = r#"{"level":"error","msg":"failed","duration_ms":1500}"#;
+
+        let template = "[{level:level}] {msg} {duration_ms:red}ms";
+        let config = test_config_with(&["--color=always", "--template", template]);
+        let output = render(input, config);
+
+        assert_eq!(
+            "[\u{1b}[31merror\u{1b}[0m] failed \u{1b}[31m1500\u{1b}[0mms\n",
+            output
+        );
+    }
+
+    #[test]
+    fn test_no_blank_lines() {
+        let input: &[u8] = b"{\"msg\":\"one\"}\n{}\nbad \xff\n{\"msg\":\"two\"}\n";
+
+        let mut output = Vec::new();
+        transform_lines(input, &mut output, test_config());
+        assert_eq!("one\n\n\ntwo\n", String::from_utf8(output).unwrap());
+
+        let mut output = Vec::new();
+        transform_lines(input, &mut output, test_config_with(&["--no-blank-lines"]));
+        assert_eq!("one\ntwo\n", String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn test_no_blank_lines_keeps_rendered_blank_lines() {
+        let input = "{\"msg\":\"one\"}\n\n{}\n{\"msg\":\"two\",\"body\":\"a\\n\\nb\"}";
+
+        let output = render(input, test_config_with(&["--no-blank-lines", "--spacing"]));
+        assert_eq!("one\n\ntwo\nbody=a\n\nb\n\n", output);
+    }
+
+    #[test]
+    fn test_fractional_epoch_string() {
+        let input = r#"{"timestamp":"1627494000.5","msg":"string"}
+{"timestamp":1627494000.25,"msg":"number"}
+{"timestamp":"1627494000","msg":"whole"}
+{"timestamp":"soon","msg":"not a number"}"#;
+
+        let expected = "2021-07-28T17:40:00.500Z string
+2021-07-28T17:40:00.250Z number
+2021-07-28T17:40:00Z whole
+soon not a number
+";
+        assert_eq!(expected, render(input, test_config()));
+    }
+
+    #[test]
+    fn test_detect_and_skip_ansi_lines() {
+        let input = "\u{1b}[32mOK\u{1b}[0m build <passed>\nplain <line>\n{\"msg\":\"json\"}";
+
+        let config = test_config_with(&[
+            "--output-format=html",
+            "--passthrough-prefix=> ",
+            "--detect-and-skip-ansi-lines",
+        ]);
+        let output = render(input, config);
+
+        let expected = "<pre>
+OK build &lt;passed&gt;
+&gt; plain &lt;line&gt;
+json
+</pre>
+";
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_hex_field() {
+        let input = r#"{"msg":"set","flags":255,"addr":18446744073709551615,"delta":-255,"ratio":0.5,"other":255}"#;
+
+        let config = test_config_with(&[
+            "--hex-field=flags",
+            "--hex-field=addr",
+            "--hex-field=delta",
+            "--hex-field=ratio",
+        ]);
+        let output = render(input, config);
+
+        let expected = "set flags=0xff addr=0xffffffffffffffff delta=-0xff ratio=0.5 other=255\n";
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_decode_base64() {
+        let input = r#"{"msg":"cmVxdWVzdCBmaW5pc2hlZCBpbiA1bXM=","payload":"aGVsbG8=","binary":"//79","plain":"not base64!"}"#;
+
+        let config = test_config_with(&[
+            "--decode-base64=msg",
+            "--decode-base64=payload",
+            "--decode-base64=binary",
+            "--decode-base64=plain",
+        ]);
+        let output = render(input, config);
+
+        let expected = "request finished in 5ms payload=hello binary=//79 plain=\"not base64!\"\n";
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_no_fields_leak_between_records() {
+        let mut input = String::new();
+        let mut expected = String::new();
+        for i in 0..200 {
+            match i % 5 {
+                0 => input.push_str(&format!("{{\"msg\":\"object\",\"a{i}\":{i}}}\n")),
+                1 => input.push_str("plain text\n"),
+                2 => input.push_str("[1,2,3]\n"),
+                3 => input.push_str(&format!("{{\"leaked{i}\":true, broken\n")),
+                _ => input.push_str(&format!("{{\"b{i}\":\"multi\\nline\"}}\n")),
+            }
+            match i % 5 {
+                0 => expected.push_str(&format!("object a{i}={i}\n")),
+                1 => expected.push_str("plain text\n"),
+                2 => expected.push_str("[1,2,3]\n"),
+                3 => expected.push_str(&format!("{{\"leaked{i}\":true, broken\n")),
+                _ => expected.push_str(&format!("\nb{i}=multi\nline\n")),
+            }
+        }
+
+        assert_eq!(expected, render(&input, test_config()));
+    }
+
+    #[test]
+    fn test_unflatten_dotted_output() {
+        let input = r#"{"msg":"hi","http.method":"GET","user":7,"http.status":200,"user.id":7,"a.b.c":true}
+not json"#;
+
+        let output = render(input, test_config_with(&["--output-format=json"]));
+        let expected = r#"{"msg":"hi","http.method":"GET","user":7,"http.status":200,"user.id":7,"a.b.c":true}
+not json
+"#;
+        assert_eq!(expected, output);
+
+        let config = test_config_with(&["--output-format=json", "--unflatten-dotted-output"]);
+        let output = render(input, config);
+        let expected = r#"{"msg":"hi","http":{"method":"GET","status":200},"user":7,"user.id":7,"a":{"b":{"c":true}}}
+not json
+"#;
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_sort_keys() {
+        let input = r#"{"msg":"sorted","zeta":1,"meta":{"z":1,"a":{"y":2,"b":3}},"alpha":2}"#;
+
+        let output = render(input, test_config_with(&["--sort-nested-keys"]));
+        assert_eq!("sorted zeta=1 meta{a{b=3 y=2} z=1} alpha=2\n", output);
+
+        let output = render(input, test_config_with(&["--sort-keys"]));
+        assert_eq!("sorted alpha=2 meta{z=1 a{y=2 b=3}} zeta=1\n", output);
+    }
+
+    #[test]
+    fn test_color_null_and_bool() {
+        let input = r#"{"msg":"checked","user":null,"ok":false,"cached":true}"#;
+
+        let config = test_config_with(&[
+            "--color=always",
+            "--color-null=gray",
+            "--color-bool=green,red",
+        ]);
+        let output = render(input, config);
+
+        let expected = "checked \u{1b}[34muser\u{1b}[0m=\u{1b}[90mnull\u{1b}[0m \
+                        \u{1b}[34mok\u{1b}[0m=\u{1b}[31mfalse\u{1b}[0m \
+                        \u{1b}[34mcached\u{1b}[0m=\u{1b}[32mtrue\u{1b}[0m\n";
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_lookup() {
+        let input = r#"{"msg":"GET /","status":200,"result":"ok"}
+{"msg":"GET /missing","status":404,"result":"err"}
+{"msg":"GET /teapot","status":418,"result":"??"}"#;
+
+        let config = test_config_with(&[
+            "--lookup=status:200=OK,404=Not Found",
+            "--lookup=result:ok=✓,err=✗",
+        ]);
+        let output = render(input, config);
+
+        let expected = r#"GET / status=OK result=✓
+GET /missing status="Not Found" result=✗
+GET /teapot status=418 result=??
+"#;
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_trace_field() {
+        let input = r#"{"msg":"failed","stacktrace":"java.lang.IllegalStateException: boom\n\tat com.example.Foo.bar(Foo.java:10)\n\tat com.example.Main.main(Main.java:5)","user":"bob"}
+{"msg":"one line","exception":"java.lang.RuntimeException: oops\tat a.B.c(B.java:1)\tat a.D.e(D.java:2)"}"#;
+
+        let config = test_config_with(&["--trace-field=stacktrace", "--trace-field=exception"]);
+        let output = render(input, config);
+
+        let expected = "failed user=bob
+stacktrace:
+    java.lang.IllegalStateException: boom
+        at com.example.Foo.bar(Foo.java:10)
+        at com.example.Main.main(Main.java:5)
+one line
+exception:
+    java.lang.RuntimeException: oops
+        at a.B.c(B.java:1)
+        at a.D.e(D.java:2)
+";
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_hide_info_level() {
+        let input = r#"{"timestamp":1627494000,"level":"INFO","msg":"started"}
+{"timestamp":1627494001,"level":"warn","msg":"slow"}
+{"timestamp":1627494002,"level":"error","msg":"failed"}"#;
+
+        let output = render(input, test_config_with(&["--hide-info-level"]));
+
+        let expected = "2021-07-28T17:40:00Z started
+2021-07-28T17:40:01Z warn slow
+2021-07-28T17:40:02Z error failed
+";
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_inline() {
+        let input = r#"{"msg":"x","context":{"a":1,"b":{"c":2}},"user":"bob","empty":{}}"#;
+
+        let config = test_config_with(&["--inline=context", "--inline=empty"]);
+        let output = render(input, config);
+
+        assert_eq!("x a=1 b{c=2} user=bob\n", output);
+    }
+
+    #[test]
+    fn test_bool_as_flag() {
+        let input = r#"{"msg":"request","debug":true,"cached":false,"opts":{"retry":true,"gzip":false},"list":[true,false]}"#;
+
+        let output = render(input, test_config_with(&["--bool-as-flag"]));
+
+        assert_eq!("request debug opts{retry} list[true false]\n", output);
+    }
+
+    #[test]
+    fn test_show_sizes() {
+        let input = r#"{"msg":"order","items":["a","b","c"],"meta":{"id":1,"tags":[]}}"#;
+
+        let output = render(input, test_config_with(&["--show-sizes"]));
+
+        assert_eq!("order items[3][a b c] meta[2]{id=1 tags[0][]}\n", output);
+    }
+
+    #[test]
+    fn test_exclude_fields() {
+        let input = r#"{"level":"info","msg":"handled","span":{"id":"abc","parent":"def"}}
+{"level":"warn","msg":"slow","thread":"main","note":"multi\nline","user":"bob"}"#;
+
+        let config = test_config_with(&["--exclude-fields=span,thread,note,level"]);
+        let output = render(input, config);
+
+        assert_eq!("handled\nslow user=bob\n", output);
+
+        // msg is a --no-key-fields field, excluding it wins
+        let config = test_config_with(&["--exclude-fields=msg,level"]);
+        let output = render(r#"{"level":"info","msg":"handled","user":"bob"}"#, config);
+
+        assert_eq!("user=bob\n", output);
+
+        let input = r#"{"level":"info","msg":"hi","span_id":"x","a":{"b":1}}"#;
+        let config = test_config_with(&["--exclude-fields=span_id", "--output-format=json"]);
+        assert_eq!(
+            "{\"level\":\"info\",\"msg\":\"hi\",\"a\":{\"b\":1}}\n",
+            render(input, config)
+        );
+
+        let config =
+            test_config_with(&["--exclude-fields=span_id", "--template", "{msg}[{span_id}]"]);
+        assert_eq!("hi[]\n", render(input, config));
+    }
+
+    #[test]
+    fn test_discover_schema() {
+        let input = r#"{"level":"info","msg":"started","port":8080}
+not json
+{"level":"warn","msg":"slow","port":"http","http":{"status":200,"tags":["a","b"]}}
+{"level":"error","msg":null,"http":{"status":500}}"#;
+
+        let config = test_config_with(&["--discover-schema"]);
+        let output = render(input, config);
+
+        let expected = r#"level       string "info"
+msg         string|null "started"
+port        number|string 8080
+http        object {"status":200,"tags":["a","b"]}
+http.status number 200
+http.tags   array ["a","b"]
+http.tags[] string "a"
+"#;
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_only_fields() {
+        let input = r#"{"timestamp":1627494000,"level":"info","msg":"served","latency_ms":12,"path":"/"}
+{"user":"bob"}"#;
+
+        let config = test_config_with(&["--only-fields=latency_ms,msg"]);
+        let output = render(input, config);
+
+        assert_eq!("served latency_ms=12\n\n", output);
+    }
+
+    #[test]
+    fn test_limit() {
+        let input = r#"starting up
+{"msg":"one"}
+{"msg":"two"}
+not json
+{"msg":"three"}
+{"msg":"four"}"#;
+
+        let config = test_config_with(&["--limit=3"]);
+        let output = render(input, config);
+
+        assert_eq!("starting up\none\ntwo\nnot json\nthree\n", output);
+    }
+
+    #[test]
+    fn test_min_level_hides_lower_levels() {
+        let input = r#"{"level":"debug","msg":"cache miss"}
+{"level":"error","msg":"request failed"}
+{"msg":"no level"}
+{"level":"verbose","msg":"unknown level"}"#;
+
+        let config = test_config_with(&["--min-level=warn"]);
+        let output = render(input, config);
+
+        assert_eq!(
+            "error request failed\nno level\nverbose unknown level\n",
+            output
+        );
+    }
+
+    #[test]
+    fn test_timezone() {
+        let input = r#"{"timestamp":1627494000,"level":"info","msg":"summer"}
+{"timestamp":1640995200,"level":"info","msg":"winter"}"#;
+        let expected = "2021-07-28T13:40:00-04:00 info summer\n\
+2021-12-31T19:00:00-05:00 info winter\n";
+
+        let config = test_config_with(&["--timezone=America/New_York"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+        let output = render(input, test_config_with(&["--timezone=UTC"]));
+        assert!(output.starts_with("2021-07-28T17:40:00Z info summer\n"));
+        assert!(cfg::Args::try_parse_from(["jsonlogprint", "--timezone=Mars/Olympus"]).is_err());
+    }
+
+    #[test]
+    fn test_color_by_type() {
+        let input = r#"{"msg":"checked","user":"bob","port":8080,"ok":true,"parent":null}"#;
+
+        let config = test_config_with(&["--color=always", "--color-by-type"]);
+        let output = render(input, config);
+
+        let expected = "checked \u{1b}[34muser\u{1b}[0m=\u{1b}[32mbob\u{1b}[0m \
+                        \u{1b}[34mport\u{1b}[0m=\u{1b}[34m8080\u{1b}[0m \
+                        \u{1b}[34mok\u{1b}[0m=\u{1b}[35mtrue\u{1b}[0m \
+                        \u{1b}[34mparent\u{1b}[0m=\u{1b}[90mnull\u{1b}[0m\n";
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_require() {
+        let input = r#"{"msg":"with id","request_id":"abc"}
+{"msg":"without id"}
+not json
+{"msg":"nested id","context":{"request_id":"def"}}"#;
+
+        let config = test_config_with(&["--require=request_id"]);
+        let output = render(input, config);
+        assert_eq!("with id request_id=abc\nnot json\n", output);
+
+        let config = test_config_with(&["--require=context.request_id"]);
+        let output = render(input, config);
+        assert_eq!("not json\nnested id context{request_id=def}\n", output);
+    }
+
+    #[test]
+    fn test_ts_out_format() {
+        let input = r#"{"timestamp":1627494000,"level":"info","msg":"seconds"}
+{"timestamp":1627494000123,"level":"info","msg":"millis"}"#;
+        let expected = "17:40:00.000 info seconds\n17:40:00.123 info millis\n";
+
+        let config = test_config_with(&["--timestamp-format=auto", "--ts-out-format=%H:%M:%S%.3f"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+        assert!(cfg::Args::try_parse_from(["jsonlogprint", "--ts-out-format=%H:%Q"]).is_err());
+    }
+
+    #[test]
+    fn test_rfc3339_string_timestamp() {
+        let input = r#"{"timestamp":"2021-07-28T17:40:00Z","level":"info","msg":"utc"}
+{"timestamp":"2021-07-28T19:40:00.250+02:00","level":"info","msg":"offset"}
+{"timestamp":"yesterday","level":"info","msg":"unparseable"}"#;
+        let expected = "2021-07-28T17:40:00Z info utc\n\
+2021-07-28T17:40:00.250Z info offset\n\
+yesterday info unparseable\n";
+
+        let output = render(input, test_config());
+        assert_eq!(expected, output);
+
+        let config = test_config_with(&["--color=always"]);
+        let output = render(r#"{"timestamp":"2021-07-28T17:40:00Z"}"#, config);
+        assert_eq!("\u{1b}[2m2021-07-28T17:40:00Z\u{1b}[0m\n", output);
+    }
+
+    #[test]
+    fn test_watch() {
+        let input = r#"{"msg":"poll","status":"ok"}
+{"msg":"poll","status":"ok"}
+{"msg":"no status"}
+{"msg":"poll","status":"ok"}
+{"msg":"poll","status":"degraded"}
+{"msg":"poll","status":"ok"}"#;
+
+        let config = test_config_with(&["--watch=status"]);
+        let output = render(input, config);
+
+        assert_eq!(
+            "poll status=ok\npoll status=degraded\npoll status=ok\n",
+            output
+        );
+    }
+
+    #[test]
+    fn test_nanos_timestamps() {
+        let input = r#"{"timestamp":1627494000123456789,"msg":"nanos"}
+{"timestamp":100000000000000001,"msg":"smallest auto nanos"}
+{"timestamp":99999999999999999,"msg":"too large for millis"}
+{"timestamp":-1000000000000000000,"msg":"before 1970"}"#;
+        let expected = "2021-07-28T17:40:00.123Z nanos\n\
+1973-03-03T09:46:40.000Z smallest auto nanos\n\
+99999999999999999 too large for millis\n\
+1938-04-24T22:13:20.000Z before 1970\n";
+
+        let output = render(input, test_config_with(&["--timestamp-format=auto"]));
+        assert_eq!(expected, output);
+
+        let config = test_config_with(&["--timestamp-format=nanos", "--subsecond=9"]);
+        let output = render(r#"{"timestamp":1627494000123456789}"#, config);
+        assert_eq!("2021-07-28T17:40:00.123456789Z\n", output);
+    }
+
+    #[test]
+    fn test_shorten_paths() {
+        let input = r#"{"msg":"compiling","file":"/home/ci/work/project/crates/core/src/lib.rs","module":"src/a/b/c/main.rs","short":"src/main.rs","url":"https://example.com/a/b/c/d","text":"a / b / c / d / e"}"#;
+        let expected =
+            "compiling file=/home/…/src/lib.rs module=src/…/c/main.rs short=src/main.rs \
+                        url=https://example.com/a/b/c/d text=\"a / b / c / d / e\"\n";
+
+        let config = test_config_with(&["--shorten-paths"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_no_color() {
+        // html output is colored with --color=auto unless NO_COLOR is set
+        let colorize = |color, no_color: Option<&str>| {
+            let config = test_config_with(&[color, "--output-format=html"]);
+            Styler::should_colorize(&config, no_color.map(std::ffi::OsStr::new))
+        };
+
+        assert!(!colorize("--color=auto", Some("1")));
+        assert!(colorize("--color=always", Some("1")));
+        assert!(colorize("--color=auto", Some("")));
+        assert!(colorize("--color=auto", None));
+    }
+
+    #[test]
+    fn test_message_fields() {
+        let input = r#"{"level":"info","text":"only text","user":"bob"}
+{"level":"info","message":"message wins","text":"shown with its key"}
+{"level":"info","msg":"msg wins","message":"shown with its key"}
+{"level":"info","user":"bob"}"#;
+        let expected = "info only text user=bob\n\
+info message wins text=\"shown with its key\"\n\
+info msg wins message=\"shown with its key\"\n\
+info user=bob\n";
+
+        let config = test_config_with(&["--message-fields=msg,message,text"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_max_value_width() {
+        let blob = "é".repeat(200);
+        let input = format!(
+            r#"{{"msg":"uploaded","body":"{blob}","name":"short","trace":"line one\nline two"}}"#
+        );
+        let expected = format!(
+            "uploaded body={}…(180 more) name=short\ntrace=\"line one\nline two\"\n",
+            "é".repeat(20)
+        );
+
+        let config = test_config_with(&["--max-value-width=20"]);
+        let output = render(&input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_depth_colors() {
+        let input = r#"{"msg":"nested","a":{"b":{"c":{"d":{"e":{"f":{"g":1}}}}}}}"#;
+        let expected = "nested \u{1b}[34ma{\u{1b}[0m\u{1b}[36mb{\u{1b}[0m\u{1b}[32mc{\u{1b}[0m\
+                        \u{1b}[34;2md{\u{1b}[0m\u{1b}[36;2me{\u{1b}[0m\u{1b}[32;2mf{\u{1b}[0m\
+                        \u{1b}[34mg\u{1b}[0m=1\
+                        \u{1b}[32;2m}\u{1b}[0m\u{1b}[36;2m}\u{1b}[0m\u{1b}[34;2m}\u{1b}[0m\
+                        \u{1b}[32m}\u{1b}[0m\u{1b}[36m}\u{1b}[0m\u{1b}[34m}\u{1b}[0m\n";
+
+        let output = render(input, test_config_with(&["--color=always"]));
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_max_depth_nested() {
+        let input = r#"{"msg":"deep","a":{"b":{"c":{"d":1}},"e":[1,[2]]}}"#;
+
+        let output = render(input, test_config_with(&["--max-depth=1"]));
+        assert_eq!("deep a{b{…1 key} e[…2 items]}\n", output);
+
+        // The placeholders keep the color of their depth
+        let output = render(
+            input,
+            test_config_with(&["--max-depth=1", "--color=always"]),
+        );
+        let expected = "deep \u{1b}[34ma{\u{1b}[0m\u{1b}[36mb{…1 key}\u{1b}[0m \
+                        \u{1b}[36me[…2 items]\u{1b}[0m\u{1b}[34m}\u{1b}[0m\n";
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_dual_timestamp() {
+        let input = r#"{"timestamp":1627494000,"level":"info","msg":"seconds"}
+{"timestamp":1627494000123.5,"level":"info","msg":"fractional millis"}
+{"timestamp":"2021-07-28T17:40:00Z","level":"info","msg":"already formatted"}"#;
+        let expected = "2021-07-28T17:40:00Z(1627494000) info seconds\n\
+2021-07-28T17:40:00.124Z(1627494000123.5) info fractional millis\n\
+2021-07-28T17:40:00Z info already formatted\n";
+
+        let config = test_config_with(&["--timestamp-format=auto", "--dual-timestamp"]);
+        let output = render(input, config);
+
+        assert_eq!(expected, output);
+    }
+
+    #[test]
+    fn test_numeric_levels() {
+        let input = r#"{"level":50,"msg":"pino error"}
+{"level":20,"msg":"pino debug"}
+{"level":35,"msg":"custom"}
+{"level":99,"msg":"unknown"}"#;
+
+        let config = test_config_with(&["--color=always"]);
+        let output = render(r#"{"level":50,"msg":"boom"}"#, config);
+        assert_eq!("\u{1b}[31merror\u{1b}[0m boom\n", output);
+
+        let config = test_config_with(&["--level-map=35=warn,20=info", "--min-level=info"]);
+        let output = render(input, config);
+        assert_eq!(
+            "error pino error\ninfo pino debug\nwarn custom\n99 unknown\n",
+            output
+        );
+    }
+
+    #[test]
+    fn test_multiline() {
+        let input = r#"{
+  "level": "info",
+  "msg": "pretty"
+}
+{
+  "level": "warn",
+{"level":"error","msg":"interrupted"}
+{
+  "unclosed": true
+
+plain"#;
+        let expected = r#"info pretty
+{
+  "level": "warn",
+error interrupted
+{
+  "unclosed": true
+
+plain
+"#;
+
+        let output = render(input, test_config_with(&["--multiline"]));
+        assert_eq!(expected, output);
+
+        // Only the first lines of a huge unclosed object are joined
+        let lines = 1500;
+        let input = format!("{{\n{}", "\"a\": 1,\n".repeat(lines - 1));
+        let output = render(&input, test_config_with(&["--multiline", "--count-lines"]));
+        let passthrough = lines - combine::MAX_MULTILINE_LINES;
+        assert_eq!(
+            format!("records=0 passthrough={passthrough} errors=1\n"),
+            output
+        );
+    }
+
+    #[test]
+    fn test_flatten_depth() {
+        let input = r#"{"msg":"hi","http":{"status":200,"req":{"method":"GET","path":"/"}},"tags":[{"a":{"b":1}}]}"#;
+
+        let output = render(input, test_config_with(&["--flatten-depth=1"]));
+        assert_eq!(
+            "hi http.status=200 http.req{method=GET path=/} tags[{a{b=1}}]\n",
+            output
+        );
+
+        let output = render(input, test_config_with(&["--flatten-depth=2"]));
+        assert_eq!(
+            "hi http.status=200 http.req.method=GET http.req.path=/ tags[{a{b=1}}]\n",
+            output
+        );
+
+        let output = render(input, test_config_with(&["--flatten-depth=0"]));
+        assert_eq!(
+            "hi http{status=200 req{method=GET path=/}} tags[{a{b=1}}]\n",
+            output
+        );
+    }
+
+    #[test]
+    fn test_flatten_depth_fields_are_top_level() {
+        let input = r#"{"msg":"hi","http":{"status":200},"opts":{"quiet":false},"n":1}"#;
+
+        let config = test_config_with(&["--flatten-depth=1", "--hex-field=http.status"]);
+        assert_eq!(
+            "hi http.status=0xc8 opts.quiet=false n=1\n",
+            render(input, config)
+        );
+
+        // An object with only hidden values doesn't leave an extra separator
+        let config = test_config_with(&["--flatten-depth=1", "--bool-as-flag", "--separator=|"]);
+        assert_eq!("hi|http.status=200|opts{}|n=1\n", render(input, config));
+
+        let config = test_config_with(&["--flatten-depth=1", "--color=always"]);
+        assert_eq!(
+            "hi \u{1b}[34mhttp.status\u{1b}[0m=200 \u{1b}[34mopts.quiet\u{1b}[0m=false \u{1b}[34mn\u{1b}[0m=1\n",
+            render(input, config)
+        );
+    }
 }
@@ -0,0 +1,133 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use crate::styler::Level;
+
+/// Where rendered records are written
+pub(crate) struct OutputSink<'a> {
+    out: Box<dyn Write + 'a>,
+    /// Error and critical records are written here instead of `out`, if set
+    errors: Option<Box<dyn Write + 'a>>,
+    /// Records are written to a file per level instead of `out`, if set
+    by_level: Option<LevelFiles>,
+}
+
+/// A file for each level, created the first time a record with that level is
+/// written
+struct LevelFiles {
+    dir: PathBuf,
+    /// Indexed by `file_index`
+    files: [Option<BufWriter<File>>; 7],
+    /// Text written to all streams before the first record, which is written
+    /// to the start of each file as it is created
+    preamble: String,
+    started: bool,
+}
+
+impl LevelFiles {
+    /// The file for `level`, with records without a level in the last one
+    fn file_index(level: Option<Level>) -> usize {
+        level.map_or(6, |level| level as usize)
+    }
+
+    fn file(&mut self, level: Option<Level>) -> io::Result<&mut BufWriter<File>> {
+        self.started = true;
+        let file = &mut self.files[Self::file_index(level)];
+        if file.is_none() {
+            fs::create_dir_all(&self.dir)?;
+            let name = level.map_or("other", Level::name);
+            let mut created = BufWriter::new(File::create(self.dir.join(format!("{name}.log")))?);
+            created.write_all(self.preamble.as_bytes())?;
+            *file = Some(created);
+        }
+        Ok(file.as_mut().expect("file was just created"))
+    }
+}
+
+impl<'a> OutputSink<'a> {
+    pub(crate) fn new(out: impl Write + 'a) -> Self {
+        Self {
+            out: Box::new(out),
+            errors: None,
+            by_level: None,
+        }
+    }
+
+    /// Write error records to `errors` and everything else to `out`
+    pub(crate) fn with_errors(out: impl Write + 'a, errors: impl Write + 'a) -> Self {
+        Self {
+            out: Box::new(out),
+            errors: Some(Box::new(errors)),
+            by_level: None,
+        }
+    }
+
+    /// Write records to `info.log`, `error.log` etc. in `dir`, and records
+    /// without a level to `other.log`
+    pub(crate) fn split_by_level(dir: PathBuf) -> Self {
+        Self {
+            out: Box::new(io::sink()),
+            errors: None,
+            by_level: Some(LevelFiles {
+                dir,
+                files: Default::default(),
+                preamble: String::new(),
+                started: false,
+            }),
+        }
+    }
+
+    /// Write a rendered record, including its trailing newline
+    ///
+    /// Records are flushed immediately so that tailing logs isn't delayed.
+    pub(crate) fn write_record(&mut self, level: Option<Level>, record: &[u8]) -> io::Result<()> {
+        if let Some(by_level) = &mut self.by_level {
+            let file = by_level.file(level)?;
+            file.write_all(record)?;
+            return file.flush();
+        }
+        let sink = match (&mut self.errors, level) {
+            (Some(errors), Some(level)) if level >= Level::Error => errors,
+            _ => &mut self.out,
+        };
+        sink.write_all(record)?;
+        sink.flush()
+    }
+
+    /// Write `text` to every stream, e.g. for document headers
+    pub(crate) fn write_all_streams(&mut self, text: &str) -> io::Result<()> {
+        self.out.write_all(text.as_bytes())?;
+        if let Some(errors) = &mut self.errors {
+            errors.write_all(text.as_bytes())?;
+        }
+        if let Some(by_level) = &mut self.by_level {
+            if !by_level.started {
+                by_level.preamble.push_str(text);
+            }
+            for file in by_level.files.iter_mut().flatten() {
+                file.write_all(text.as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()?;
+        if let Some(errors) = &mut self.errors {
+            errors.flush()?;
+        }
+        if let Some(by_level) = &mut self.by_level {
+            for file in by_level.files.iter_mut().flatten() {
+                file.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, W: Write + 'a> From<W> for OutputSink<'a> {
+    fn from(out: W) -> Self {
+        Self::new(out)
+    }
+}
@@ -0,0 +1,68 @@
+use std::io::{self, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+/// The pager used when `$PAGER` isn't set, `-R` keeps the colors
+pub(crate) const DEFAULT_PAGER: &str = "less -R";
+
+/// Start a pager, like `less -R`, for --pager
+///
+/// The command is split on whitespace. Output written to the returned input
+/// is shown by the pager, which must be waited for once the input is dropped.
+pub(crate) fn spawn(command: &str) -> io::Result<(Child, PagerInput)> {
+    let mut words = command.split_whitespace();
+    let Some(program) = words.next() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "empty pager command",
+        ));
+    };
+    let mut child = Command::new(program)
+        .args(words)
+        .stdin(Stdio::piped())
+        .spawn()?;
+    let stdin = child.stdin.take().expect("stdin is piped");
+    Ok((child, PagerInput(stdin)))
+}
+
+/// The input of a pager
+///
+/// The pager closes its input when the user quits it, which quietly exits
+/// instead of failing with a broken pipe.
+pub(crate) struct PagerInput(ChildStdin);
+
+impl Write for PagerInput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        exit_if_closed(self.0.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        exit_if_closed(self.0.flush())
+    }
+}
+
+fn exit_if_closed<T>(result: io::Result<T>) -> io::Result<T> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => std::process::exit(0),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn test_spawn() {
+        let path = std::env::temp_dir().join(format!("jsonlogprint-pager-{}", std::process::id()));
+        let command = format!("dd status=none of={}", path.display());
+        let (mut child, mut input) = spawn(&command).unwrap();
+        input.write_all(b"paged\n").unwrap();
+        drop(input);
+        assert!(child.wait().unwrap().success());
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "paged\n");
+        std::fs::remove_file(path).unwrap();
+        assert!(spawn(" ").is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::deser::JsonValue;
+use crate::FnvIndexMap;
+
+/// A path to a value nested inside of a log record
+///
+/// Parsed from a JSON Pointer (RFC 6901) like `/fields/message`. Segments
+/// index into objects by key and into arrays by position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FieldPath {
+    segments: Vec<String>,
+}
+
+impl FieldPath {
+    pub(crate) fn from_pointer(pointer: &str) -> Result<Self, String> {
+        let Some(rest) = pointer.strip_prefix('/') else {
+            return Err(format!("JSON pointer {pointer:?} must start with '/'"));
+        };
+        let segments = rest
+            .split('/')
+            .map(|segment| segment.replace("~1", "/").replace("~0", "~"))
+            .collect();
+        Ok(Self { segments })
+    }
+
+    /// Parse a field name that may be a path, either a JSON Pointer or dotted
+    /// like `meta.level`
+    ///
+    /// Returns `None` for plain keys.
+    pub(crate) fn from_field(field: &str) -> Option<Self> {
+        if field.starts_with('/') {
+            Self::from_pointer(field).ok()
+        } else if field.contains('.') {
+            let segments = field.split('.').map(str::to_string).collect();
+            Some(Self { segments })
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn get<'m, 'a>(
+        &self,
+        map: &'m FnvIndexMap<&'a str, JsonValue<'a>>,
+    ) -> Option<&'m JsonValue<'a>> {
+        let (first, rest) = self.segments.split_first()?;
+        let mut value = map.get(first.as_str())?;
+        for segment in rest {
+            value = match value {
+                JsonValue::Object(map) => map.get(segment.as_str())?,
+                JsonValue::Array(array) => array.get(segment.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        match value {
+            JsonValue::Removed => None,
+            _ => Some(value),
+        }
+    }
+
+    pub(crate) fn get_mut<'m, 'a>(
+        &self,
+        map: &'m mut FnvIndexMap<&'a str, JsonValue<'a>>,
+    ) -> Option<&'m mut JsonValue<'a>> {
+        let (first, rest) = self.segments.split_first()?;
+        let mut value = map.get_mut(first.as_str())?;
+        for segment in rest {
+            value = match value {
+                JsonValue::Object(map) => map.get_mut(segment.as_str())?,
+                JsonValue::Array(array) => array.get_mut(segment.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        match value {
+            JsonValue::Removed => None,
+            _ => Some(value),
+        }
+    }
+}
+
+/// A field named in a --filter expression or a --template
+///
+/// Dotted names match a top-level key with that exact name first, and
+/// otherwise the nested path, where numbers index into arrays.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Field {
+    name: String,
+    path: Option<FieldPath>,
+}
+
+impl Field {
+    pub(crate) fn new(name: String) -> Self {
+        let path = FieldPath::from_field(&name);
+        Self { name, path }
+    }
+
+    pub(crate) fn get<'m, 'a>(
+        &self,
+        record: &'m FnvIndexMap<&'a str, JsonValue<'a>>,
+    ) -> Option<&'m JsonValue<'a>> {
+        match (record.get(self.name.as_str()), &self.path) {
+            (None, Some(path)) => path.get(record),
+            (value, _) => value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_pointer() {
+        let path = FieldPath::from_pointer("/a~1b/c~0d/0").unwrap();
+        assert_eq!(path.segments, vec!["a/b", "c~d", "0"]);
+        assert!(FieldPath::from_pointer("a/b").is_err());
+    }
+
+    #[test]
+    fn test_get_nested() {
+        let mut map = FnvIndexMap::default();
+        let seed = crate::deser::IndexMapSeed {
+            map: &mut map,
+            trim_keys: false,
+            warn_duplicates_on_line: None,
+        };
+        let mut deserializer =
+            serde_json::Deserializer::from_str(r#"{"a":{"b":[{"c":"found"}]},"d":1}"#);
+        serde::de::DeserializeSeed::deserialize(seed, &mut deserializer).unwrap();
+
+        let path = FieldPath::from_pointer("/a/b/0/c").unwrap();
+        assert!(matches!(path.get(&map), Some(JsonValue::String(s)) if s == "found"));
+        assert!(matches!(path.get_mut(&mut map), Some(JsonValue::String(s)) if s == "found"));
+        let missing = FieldPath::from_pointer("/d/e").unwrap();
+        assert!(missing.get_mut(&mut map).is_none());
+    }
+}
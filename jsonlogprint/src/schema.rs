@@ -0,0 +1,89 @@
+use std::fmt;
+
+use crate::deser::JsonValue;
+use crate::FnvIndexMap;
+
+/// The longest example value shown, in bytes
+const MAX_EXAMPLE_LEN: usize = 60;
+
+/// The fields seen in a stream of records, for --discover-schema
+///
+/// Nested fields are named by their dotted path, like `http.status`, and
+/// the elements of arrays by the path of the array followed by `[]`, like
+/// `tags[]`. Fields are kept in the order they were first seen.
+#[derive(Debug, Default)]
+pub(crate) struct Schema {
+    fields: FnvIndexMap<String, FieldSchema>,
+}
+
+#[derive(Debug)]
+struct FieldSchema {
+    /// The JSON types of the values, in the order they were first seen
+    types: Vec<&'static str>,
+    /// The first value seen, as compact JSON
+    example: String,
+}
+
+impl Schema {
+    pub(crate) fn observe(&mut self, record: &FnvIndexMap<&str, JsonValue>) {
+        for (key, value) in record {
+            self.observe_value(key.to_string(), value);
+        }
+    }
+
+    fn observe_value(&mut self, path: String, value: &JsonValue) {
+        if matches!(value, JsonValue::Removed) {
+            return;
+        }
+        let type_name = value.type_name();
+        match self.fields.get_mut(&path) {
+            Some(field) if field.types.contains(&type_name) => {}
+            Some(field) => field.types.push(type_name),
+            None => {
+                let field = FieldSchema {
+                    types: vec![type_name],
+                    example: example(value),
+                };
+                self.fields.insert(path.clone(), field);
+            }
+        }
+        match value {
+            JsonValue::Object(map) => {
+                for (key, value) in map {
+                    self.observe_value(format!("{path}.{key}"), value);
+                }
+            }
+            JsonValue::Array(array) => {
+                for element in array {
+                    self.observe_value(format!("{path}[]"), element);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A value as compact JSON, shortened to about `MAX_EXAMPLE_LEN`
+fn example(value: &JsonValue) -> String {
+    let mut example = serde_json::to_string(value).unwrap_or_default();
+    if example.len() > MAX_EXAMPLE_LEN {
+        let mut end = MAX_EXAMPLE_LEN;
+        while !example.is_char_boundary(end) {
+            end -= 1;
+        }
+        example.truncate(end);
+        example.push('…');
+    }
+    example
+}
+
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.fields.keys().map(String::len).max().unwrap_or(0);
+        for (path, field) in &self.fields {
+            let types = field.types.join("|");
+            writeln!(f, "{path:width$} {types} {}", field.example)?;
+        }
+        Ok(())
+    }
+}
@@ -1,26 +1,122 @@
-use owo_colors::OwoColorize;
+use fnv::FnvHasher;
+use owo_colors::AnsiColors;
+use owo_colors::DynColors;
 use owo_colors::Style;
-use owo_colors::StyledList;
+use std::ffi::OsStr;
 use std::fmt;
+use std::fmt::Write as _;
+use std::hash::Hasher as _;
 use supports_color::Stream;
 
-use crate::cfg::ColorOption;
+use crate::cfg::{ColorOption, Config, OutputFormat};
 
 #[derive(Debug, Clone, Copy)]
 pub(crate) struct Styler {
     pub(crate) colorize: bool,
+    pub(crate) markup: Markup,
+    /// Depths greater than this are not colored
+    pub(crate) max_depth: Option<usize>,
+}
+
+/// Colors for values that are colored by their hash
+const HASH_PALETTE: [AnsiColors; 12] = [
+    AnsiColors::Red,
+    AnsiColors::Green,
+    AnsiColors::Yellow,
+    AnsiColors::Blue,
+    AnsiColors::Magenta,
+    AnsiColors::Cyan,
+    AnsiColors::BrightRed,
+    AnsiColors::BrightGreen,
+    AnsiColors::BrightYellow,
+    AnsiColors::BrightBlue,
+    AnsiColors::BrightMagenta,
+    AnsiColors::BrightCyan,
+];
+
+/// Styles for each nesting depth, repeating for deeper values
+///
+/// Every key is styled by its depth, so these are built once rather than per
+/// key, see `benches/depth_styles.rs`.
+const DEPTH_STYLES: [Paint; 6] = [
+    Paint::new().fg(AnsiColors::Blue),
+    Paint::new().fg(AnsiColors::Cyan),
+    Paint::new().fg(AnsiColors::Green),
+    Paint::new().fg(AnsiColors::Blue).dimmed(),
+    Paint::new().fg(AnsiColors::Cyan).dimmed(),
+    Paint::new().fg(AnsiColors::Green).dimmed(),
+];
+
+/// A style picked by name, for --template placeholders like `{msg:bold}` and
+/// options like --color-null
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum NamedStyle {
+    /// The color of the level named by the text
+    Level,
+    Bold,
+    Dimmed,
+    Color(AnsiColors),
+}
+
+impl NamedStyle {
+    pub(crate) fn parse(name: &str) -> Option<Self> {
+        let style = match name {
+            "level" => NamedStyle::Level,
+            "bold" => NamedStyle::Bold,
+            "dim" => NamedStyle::Dimmed,
+            "red" => NamedStyle::Color(AnsiColors::Red),
+            "green" => NamedStyle::Color(AnsiColors::Green),
+            "yellow" => NamedStyle::Color(AnsiColors::Yellow),
+            "blue" => NamedStyle::Color(AnsiColors::Blue),
+            "magenta" => NamedStyle::Color(AnsiColors::Magenta),
+            "cyan" => NamedStyle::Color(AnsiColors::Cyan),
+            "gray" | "grey" => NamedStyle::Color(AnsiColors::BrightBlack),
+            _ => return None,
+        };
+        Some(style)
+    }
+}
+
+/// How styles are written to the output
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum Markup {
+    /// ANSI escape codes, for terminals
+    Ansi,
+    /// `<span style="...">` elements, with all text HTML-escaped
+    Html,
 }
 
 impl Styler {
-    pub(crate) fn new(when: ColorOption) -> Self {
-        let colorize = match when {
+    pub(crate) fn new(config: &Config) -> Self {
+        let format = config.output_format;
+        let markup = match format {
+            OutputFormat::Text | OutputFormat::AnsiToFile | OutputFormat::Json => Markup::Ansi,
+            OutputFormat::Html => Markup::Html,
+        };
+        Self {
+            colorize: Self::should_colorize(config, std::env::var_os("NO_COLOR").as_deref()),
+            markup,
+            max_depth: config.color_max_depth,
+        }
+    }
+
+    /// Whether to color the output, given the value of `NO_COLOR`
+    pub(crate) fn should_colorize(config: &Config, no_color: Option<&OsStr>) -> bool {
+        let format = config.output_format;
+        match config.color {
+            _ if format == OutputFormat::AnsiToFile => true,
+            _ if format == OutputFormat::Json => false,
             ColorOption::Always => true,
+            // https://no-color.org
+            ColorOption::Auto if no_color.is_some_and(|v| !v.is_empty()) => false,
+            // Whether stdout is a terminal is irrelevant for html
+            ColorOption::Auto if format == OutputFormat::Html => true,
+            ColorOption::Auto if config.output.is_some() => false,
             ColorOption::Auto => {
                 supports_color::on(Stream::Stdout).is_some() || std::env::var("CI").is_ok()
             }
             ColorOption::Never => false,
-        };
-        Self { colorize }
+        }
     }
 
     pub(crate) fn empty(self) -> CustomDisplay<'static> {
@@ -31,8 +127,21 @@ impl Styler {
         }
     }
 
-    pub(crate) fn timestamp<D: fmt::Display>(self, timestamp: &D) -> TimestampDisplay<'_, D> {
-        TimestampDisplay(self, timestamp)
+    /// Unstyled text, which still needs escaping for some markups
+    pub(crate) fn text(self, text: &str) -> CustomDisplay<'_> {
+        CustomDisplay {
+            styler: self,
+            style: DisplayStyle::Text,
+            value: text,
+        }
+    }
+
+    pub(crate) fn timestamp<D: fmt::Display>(self, timestamp: &D) -> DimmedDisplay<'_, D> {
+        DimmedDisplay(self, timestamp)
+    }
+
+    pub(crate) fn line_number<D: fmt::Display>(self, line_number: &D) -> DimmedDisplay<'_, D> {
+        DimmedDisplay(self, line_number)
     }
 
     pub(crate) fn level(self, level: &str) -> CustomDisplay<'_> {
@@ -43,10 +152,80 @@ impl Styler {
         }
     }
 
+    pub(crate) fn emphasis(self, text: &str) -> CustomDisplay<'_> {
+        CustomDisplay {
+            styler: self,
+            style: DisplayStyle::Emphasis,
+            value: text,
+        }
+    }
+
+    /// `value` in a color picked by hashing `hashed`
+    pub(crate) fn hashed<'a>(self, value: &'a str, hashed: &str) -> CustomDisplay<'a> {
+        let mut hasher = FnvHasher::default();
+        hasher.write(hashed.as_bytes());
+        let color = (hasher.finish() % HASH_PALETTE.len() as u64) as u8;
+        CustomDisplay {
+            styler: self,
+            style: DisplayStyle::Hashed(color),
+            value,
+        }
+    }
+
+    /// `value` colored from green to yellow to red as `position` goes from 0
+    /// to 1
+    pub(crate) fn gradient(self, value: &str, position: f64) -> CustomDisplay<'_> {
+        let position = position.clamp(0.0, 1.0);
+        let (red, green) = if position < 0.5 {
+            (220.0 * position * 2.0, 200.0)
+        } else {
+            (220.0, 200.0 * (1.0 - position) * 2.0)
+        };
+        CustomDisplay {
+            styler: self,
+            style: DisplayStyle::Rgb(red.round() as u8, green.round() as u8, 0),
+            value,
+        }
+    }
+
+    /// An array element that wasn't in the previous record
+    pub(crate) fn added(self, element: &str) -> CustomDisplay<'_> {
+        CustomDisplay {
+            styler: self,
+            style: DisplayStyle::Added,
+            value: element,
+        }
+    }
+
+    /// An array element that was in the previous record but is gone
+    pub(crate) fn removed(self, element: &str) -> CustomDisplay<'_> {
+        CustomDisplay {
+            styler: self,
+            style: DisplayStyle::Removed,
+            value: element,
+        }
+    }
+
+    pub(crate) fn named(self, text: &str, style: NamedStyle) -> CustomDisplay<'_> {
+        CustomDisplay {
+            styler: self,
+            style: DisplayStyle::Named(style),
+            value: text,
+        }
+    }
+
+    pub(crate) fn component(self, component: &str) -> CustomDisplay<'_> {
+        CustomDisplay {
+            styler: self,
+            style: DisplayStyle::Component,
+            value: component,
+        }
+    }
+
     pub(crate) fn depth(self, val: &str, depth: usize) -> CustomDisplay<'_> {
         CustomDisplay {
             styler: self,
-            style: DisplayStyle::Depth(depth as u16),
+            style: self.depth_display_style(depth),
             value: val,
         }
     }
@@ -59,62 +238,352 @@ impl Styler {
     ) -> CustomDisplay<'a> {
         CustomDisplay {
             styler: self,
-            style: DisplayStyle::DepthMulti(depth as u16, extra),
+            style: match self.depth_display_style(depth) {
+                DisplayStyle::Depth(depth) => DisplayStyle::DepthMulti(depth, extra),
+                _ => DisplayStyle::TextMulti(extra),
+            },
             value,
         }
     }
 
-    fn timestamp_style(&self) -> Style {
+    fn depth_display_style(self, depth: usize) -> DisplayStyle<'static> {
+        match self.max_depth {
+            Some(max_depth) if depth > max_depth => DisplayStyle::Text,
+            _ => DisplayStyle::Depth(depth as u16),
+        }
+    }
+
+    /// Markup that must surround all of the output, if any
+    pub(crate) fn document_start(self) -> &'static str {
+        match self.markup {
+            Markup::Ansi => "",
+            Markup::Html => "<pre>\n",
+        }
+    }
+
+    pub(crate) fn document_end(self) -> &'static str {
+        match self.markup {
+            Markup::Ansi => "",
+            Markup::Html => "</pre>\n",
+        }
+    }
+
+    /// This styler without colors, text is still escaped for the markup
+    pub(crate) fn plain(self) -> Self {
+        Self {
+            colorize: false,
+            ..self
+        }
+    }
+
+    /// Markup that dims everything until `dim_end`, for --dim-when
+    ///
+    /// The text in between must be written by a `plain` styler, any style
+    /// inside would end the dimming early.
+    pub(crate) fn dim_start(self) -> &'static str {
+        match (self.colorize, self.markup) {
+            (false, _) => "",
+            (true, Markup::Ansi) => "\x1b[2m",
+            (true, Markup::Html) => r#"<span style="opacity:0.6">"#,
+        }
+    }
+
+    pub(crate) fn dim_end(self) -> &'static str {
+        match (self.colorize, self.markup) {
+            (false, _) => "",
+            (true, Markup::Ansi) => "\x1b[0m",
+            (true, Markup::Html) => "</span>",
+        }
+    }
+
+    fn dimmed_style(&self) -> Paint {
         if !self.colorize {
-            return Style::new();
+            return Paint::new();
         }
-        Style::new().dimmed()
+        Paint::new().dimmed()
     }
 
-    fn depth_style(&self, depth: u16) -> Style {
+    pub(crate) fn depth_style(&self, depth: u16) -> Paint {
         if !self.colorize {
-            return Style::new();
+            return Paint::new();
         }
-        match depth % 6 {
-            0 => Style::new().blue(),
-            1 => Style::new().cyan(),
-            2 => Style::new().green(),
-            3 => Style::new().blue().dimmed(),
-            4 => Style::new().cyan().dimmed(),
-            5 => Style::new().green().dimmed(),
-            _ => Style::new(),
+        DEPTH_STYLES[usize::from(depth) % DEPTH_STYLES.len()]
+    }
+
+    fn level_style(&self, level: &str) -> Paint {
+        if !self.colorize {
+            return Paint::new();
+        }
+        match Level::parse(level) {
+            Some(Level::Critical) => Paint::new().fg(AnsiColors::Red).bold(),
+            Some(Level::Error) => Paint::new().fg(AnsiColors::Red),
+            Some(Level::Warn) => Paint::new().fg(AnsiColors::Yellow),
+            Some(Level::Info) => Paint::new().fg(AnsiColors::Cyan),
+            Some(Level::Debug) => Paint::new().fg(AnsiColors::Blue).dimmed(),
+            Some(Level::Trace) => Paint::new().dimmed(),
+            None => Paint::new(),
         }
     }
 
-    fn level_style(&self, level: &str) -> Style {
+    fn emphasis_style(&self) -> Paint {
         if !self.colorize {
-            return Style::new();
+            return Paint::new();
+        }
+        Paint::new().bold()
+    }
+
+    fn hashed_style(&self, color: u8) -> Paint {
+        if !self.colorize {
+            return Paint::new();
+        }
+        Paint::new().fg(HASH_PALETTE[color as usize])
+    }
+
+    fn rgb_style(&self, red: u8, green: u8, blue: u8) -> Paint {
+        if !self.colorize {
+            return Paint::new();
+        }
+        Paint::new().rgb(red, green, blue)
+    }
+
+    fn diff_style(&self, added: bool) -> Paint {
+        if !self.colorize {
+            return Paint::new();
+        }
+        if added {
+            Paint::new().fg(AnsiColors::Green)
+        } else {
+            Paint::new().fg(AnsiColors::Red)
+        }
+    }
+
+    fn named_style(&self, style: NamedStyle, text: &str) -> Paint {
+        match style {
+            NamedStyle::Level => self.level_style(text),
+            NamedStyle::Bold => self.emphasis_style(),
+            NamedStyle::Dimmed => self.dimmed_style(),
+            NamedStyle::Color(_) if !self.colorize => Paint::new(),
+            NamedStyle::Color(color) => Paint::new().fg(color),
         }
+    }
+
+    fn component_style(&self) -> Paint {
+        if !self.colorize {
+            return Paint::new();
+        }
+        Paint::new().fg(AnsiColors::Magenta)
+    }
+
+    /// Write `parts` to `f` with a single application of `paint`
+    fn write_painted(
+        self,
+        f: &mut fmt::Formatter<'_>,
+        paint: Paint,
+        parts: &[&dyn fmt::Display],
+    ) -> fmt::Result {
+        match self.markup {
+            Markup::Ansi => {
+                let style = paint.ansi();
+                style.fmt_prefix(f)?;
+                for part in parts {
+                    write!(f, "{part}")?;
+                }
+                style.fmt_suffix(f)
+            }
+            Markup::Html => {
+                if paint.is_plain() {
+                    for part in parts {
+                        write!(HtmlEscaper(f), "{part}")?;
+                    }
+                    return Ok(());
+                }
+                write!(f, r#"<span style="{}">"#, paint.css())?;
+                for part in parts {
+                    write!(HtmlEscaper(f), "{part}")?;
+                }
+                f.write_str("</span>")
+            }
+        }
+    }
+}
+
+/// The severity of a log record, ordered from least to most severe
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub(crate) enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Critical,
+}
+
+impl Level {
+    /// The canonical lowercase name of the level
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Level::Trace => "trace",
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+            Level::Critical => "critical",
+        }
+    }
+
+    /// Parse a level name, ignoring case
+    ///
+    /// Every comparison of level names, for coloring, filtering and detection,
+    /// goes through this so that `WARN`, `Warn` and `warn` are always the same.
+    pub(crate) fn parse(level: &str) -> Option<Level> {
         use unicase::Ascii;
         let level = Ascii::new(level);
         if level == Ascii::new("crit") || level == Ascii::new("critical") {
-            Style::new().red().bold()
+            Some(Level::Critical)
         } else if level == Ascii::new("error") {
-            Style::new().red()
+            Some(Level::Error)
         } else if level == Ascii::new("warn") || level == Ascii::new("warning") {
-            Style::new().yellow()
+            Some(Level::Warn)
         } else if level == Ascii::new("info") {
-            Style::new().cyan()
+            Some(Level::Info)
         } else if level == Ascii::new("debug") {
-            Style::new().blue().dimmed()
+            Some(Level::Debug)
         } else if level == Ascii::new("trace") {
-            Style::new().dimmed()
+            Some(Level::Trace)
         } else {
-            Style::new()
+            None
         }
     }
 }
 
+/// A style that can be rendered as either ANSI escapes or CSS
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct Paint {
+    fg: Option<DynColors>,
+    bold: bool,
+    dimmed: bool,
+}
+
+impl Paint {
+    pub(crate) const fn new() -> Self {
+        Self {
+            fg: None,
+            bold: false,
+            dimmed: false,
+        }
+    }
+
+    pub(crate) const fn fg(mut self, color: AnsiColors) -> Self {
+        self.fg = Some(DynColors::Ansi(color));
+        self
+    }
+
+    pub(crate) const fn rgb(mut self, red: u8, green: u8, blue: u8) -> Self {
+        self.fg = Some(DynColors::Rgb(red, green, blue));
+        self
+    }
+
+    pub(crate) const fn bold(mut self) -> Self {
+        self.bold = true;
+        self
+    }
+
+    pub(crate) const fn dimmed(mut self) -> Self {
+        self.dimmed = true;
+        self
+    }
+
+    fn is_plain(&self) -> bool {
+        self.fg.is_none() && !self.bold && !self.dimmed
+    }
+
+    fn ansi(&self) -> Style {
+        let mut style = Style::new();
+        if let Some(fg) = self.fg {
+            style = style.color(fg);
+        }
+        if self.bold {
+            style = style.bold();
+        }
+        if self.dimmed {
+            style = style.dimmed();
+        }
+        style
+    }
+
+    fn css(&self) -> String {
+        let mut css = Vec::with_capacity(3);
+        match self.fg {
+            Some(DynColors::Ansi(color)) => css.push(format!("color:{}", ansi_css_name(color))),
+            Some(DynColors::Rgb(r, g, b)) => css.push(format!("color:#{r:02x}{g:02x}{b:02x}")),
+            Some(DynColors::Css(_)) | Some(DynColors::Xterm(_)) | None => {}
+        }
+        if self.bold {
+            css.push("font-weight:bold".to_string());
+        }
+        if self.dimmed {
+            css.push("opacity:0.6".to_string());
+        }
+        css.join(";")
+    }
+}
+
+fn ansi_css_name(color: AnsiColors) -> &'static str {
+    match color {
+        AnsiColors::Black => "black",
+        AnsiColors::Red => "red",
+        AnsiColors::Green => "green",
+        AnsiColors::Yellow => "olive",
+        AnsiColors::Blue => "blue",
+        AnsiColors::Magenta => "purple",
+        AnsiColors::Cyan => "teal",
+        AnsiColors::White => "silver",
+        AnsiColors::Default => "inherit",
+        AnsiColors::BrightBlack => "gray",
+        AnsiColors::BrightRed => "tomato",
+        AnsiColors::BrightGreen => "lime",
+        AnsiColors::BrightYellow => "gold",
+        AnsiColors::BrightBlue => "dodgerblue",
+        AnsiColors::BrightMagenta => "fuchsia",
+        AnsiColors::BrightCyan => "aqua",
+        AnsiColors::BrightWhite => "white",
+    }
+}
+
+/// Escapes everything written through it for inclusion in HTML text
+struct HtmlEscaper<'a, 'b>(&'a mut fmt::Formatter<'b>);
+
+impl fmt::Write for HtmlEscaper<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut rest = s;
+        while let Some(idx) = rest.find(['&', '<', '>']) {
+            self.0.write_str(&rest[..idx])?;
+            self.0.write_str(match rest.as_bytes()[idx] {
+                b'&' => "&amp;",
+                b'<' => "&lt;",
+                _ => "&gt;",
+            })?;
+            rest = &rest[idx + 1..];
+        }
+        self.0.write_str(rest)
+    }
+}
+
 enum DisplayStyle<'a> {
     Empty,
+    Text,
+    TextMulti(&'a str),
     Depth(u16),
     DepthMulti(u16, &'a str),
     Level,
+    Component,
+    Emphasis,
+    /// An index into the `HASH_PALETTE`
+    Hashed(u8),
+    Rgb(u8, u8, u8),
+    Added,
+    Removed,
+    Named(NamedStyle),
 }
 
 pub(crate) struct CustomDisplay<'a> {
@@ -125,30 +594,50 @@ pub(crate) struct CustomDisplay<'a> {
 
 impl<'a> fmt::Display for CustomDisplay<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let styler = self.styler;
         match self.style {
             DisplayStyle::Empty => Ok(()),
+            DisplayStyle::Text => styler.write_painted(f, Paint::new(), &[&self.value]),
+            DisplayStyle::TextMulti(second) => {
+                styler.write_painted(f, Paint::new(), &[&self.value, &second])
+            }
             DisplayStyle::Depth(depth) => {
-                write!(f, "{}", self.value.style(self.styler.depth_style(depth)))
+                styler.write_painted(f, styler.depth_style(depth), &[&self.value])
             }
             DisplayStyle::DepthMulti(depth, second) => {
-                let key = self.value.style(self.styler.depth_style(depth));
-                let key2 = second.style(self.styler.depth_style(depth));
-                write!(f, "{}", StyledList::from([key, key2]))
+                styler.write_painted(f, styler.depth_style(depth), &[&self.value, &second])
+            }
+            DisplayStyle::Level => {
+                styler.write_painted(f, styler.level_style(self.value), &[&self.value])
+            }
+            DisplayStyle::Hashed(color) => {
+                styler.write_painted(f, styler.hashed_style(color), &[&self.value])
+            }
+            DisplayStyle::Rgb(red, green, blue) => {
+                styler.write_painted(f, styler.rgb_style(red, green, blue), &[&self.value])
+            }
+            DisplayStyle::Emphasis => {
+                styler.write_painted(f, styler.emphasis_style(), &[&self.value])
+            }
+            DisplayStyle::Added => styler.write_painted(f, styler.diff_style(true), &[&self.value]),
+            DisplayStyle::Removed => {
+                styler.write_painted(f, styler.diff_style(false), &[&self.value])
+            }
+            DisplayStyle::Named(style) => {
+                styler.write_painted(f, styler.named_style(style, self.value), &[&self.value])
+            }
+            DisplayStyle::Component => {
+                styler.write_painted(f, styler.component_style(), &[&self.value])
             }
-            DisplayStyle::Level => write!(
-                f,
-                "{}",
-                self.value.style(self.styler.level_style(self.value))
-            ),
         }
     }
 }
 
 // TODO: Maybe move this into DisplayStyle? makes it uglier and it's not necessary now
-pub(crate) struct TimestampDisplay<'a, D: fmt::Display>(Styler, &'a D);
+pub(crate) struct DimmedDisplay<'a, D: fmt::Display>(Styler, &'a D);
 
-impl<'a, D: fmt::Display> fmt::Display for TimestampDisplay<'a, D> {
+impl<'a, D: fmt::Display> fmt::Display for DimmedDisplay<'a, D> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.1.style(self.0.timestamp_style()))
+        self.0.write_painted(f, self.0.dimmed_style(), &[self.1])
     }
 }
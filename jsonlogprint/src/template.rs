@@ -0,0 +1,130 @@
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+use crate::deser::JsonValue;
+use crate::path::Field;
+use crate::styler::{NamedStyle, Styler};
+use crate::FnvIndexMap;
+
+/// A --template like `{timestamp} [{level}] {msg}`, parsed once at startup
+///
+/// `{field}` is replaced by the value of the field, or nothing if the record
+/// doesn't have it. Fields may be paths like `{context.request_id}` or
+/// `{items.0}`. `{{` and `}}` are literal braces. A style may follow the
+/// field, like `{level:level}` or `{duration_ms:red}`.
+#[derive(Debug, Clone)]
+pub(crate) struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Field(Field, Option<NamedStyle>),
+}
+
+impl Template {
+    pub(crate) fn parse(template: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = template;
+        while let Some(index) = rest.find(['{', '}']) {
+            literal.push_str(&rest[..index]);
+            let brace = &rest[index..index + 1];
+            rest = &rest[index + 1..];
+            if let Some(after) = rest.strip_prefix(brace) {
+                literal.push_str(brace);
+                rest = after;
+                continue;
+            }
+            if brace == "}" {
+                return Err(format!("unmatched '}}' in template {template:?}"));
+            }
+            let Some(end) = rest.find('}') else {
+                return Err(format!("unclosed '{{' in template {template:?}"));
+            };
+            let field = &rest[..end];
+            if field.is_empty() || field.contains('{') {
+                return Err(format!("invalid placeholder {{{field}}} in template"));
+            }
+            if !literal.is_empty() {
+                parts.push(Part::Literal(std::mem::take(&mut literal)));
+            }
+            let (field, style) = match field.rsplit_once(':') {
+                Some((field, style)) => match NamedStyle::parse(style) {
+                    Some(style) => (field, Some(style)),
+                    None => return Err(format!("unknown style {style:?} in template")),
+                },
+                None => (field, None),
+            };
+            parts.push(Part::Field(Field::new(field.to_string()), style));
+            rest = &rest[end + 1..];
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Self { parts })
+    }
+
+    pub(crate) fn render(
+        &self,
+        out: &mut impl Write,
+        record: &FnvIndexMap<&str, JsonValue>,
+        styler: Styler,
+    ) -> io::Result<()> {
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => write!(out, "{}", styler.text(literal))?,
+                Part::Field(field, style) => {
+                    let Some(text) = field.get(record).map(value_text).transpose()? else {
+                        continue;
+                    };
+                    match style {
+                        Some(style) => write!(out, "{}", styler.named(&text, *style))?,
+                        None => write!(out, "{}", styler.text(&text))?,
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A value as it is in the record, strings without quotes
+fn value_text<'v>(value: &'v JsonValue) -> io::Result<Cow<'v, str>> {
+    let text = match value {
+        JsonValue::String(s) => Cow::Borrowed(&**s),
+        JsonValue::Number(n) => Cow::Owned(n.to_string()),
+        JsonValue::Bool(b) => Cow::Owned(b.to_string()),
+        JsonValue::Null => Cow::Borrowed("null"),
+        JsonValue::Removed => Cow::Borrowed(""),
+        JsonValue::Object(_) | JsonValue::Array(_) => Cow::Owned(serde_json::to_string(value)?),
+    };
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let template = Template::parse("{{{ts}}} [{level:level}]{msg}").unwrap();
+        assert_eq!(
+            template.parts,
+            vec![
+                Part::Literal("{".to_string()),
+                Part::Field(Field::new("ts".to_string()), None),
+                Part::Literal("} [".to_string()),
+                Part::Field(Field::new("level".to_string()), Some(NamedStyle::Level)),
+                Part::Literal("]".to_string()),
+                Part::Field(Field::new("msg".to_string()), None),
+            ]
+        );
+        assert!(Template::parse("{msg").is_err());
+        assert!(Template::parse("msg}").is_err());
+        assert!(Template::parse("{}").is_err());
+        assert!(Template::parse("{msg:sparkly}").is_err());
+    }
+}